@@ -0,0 +1,97 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rand::Rng;
+use sha3::{digest::ExtendableOutput, Shake256};
+use sumhash::compress::{Compressor, ConstantTimeMatrix, Matrix};
+
+// NestedLookupTable is the row-major `Vec<Vec<[u64; 256]>>` layout that LookupTable used to have,
+// kept as a baseline for the flattened column-major layout.
+struct NestedLookupTable {
+    lookup_table: Vec<Vec<[u64; 256]>>,
+}
+
+impl NestedLookupTable {
+    fn random(n: usize, m: usize) -> Self {
+        let mut rand = Shake256::default().finalize_xof();
+        let mut word = [0u8; 8];
+        let lookup_table = (0..n)
+            .map(|_| {
+                (0..m / 8)
+                    .map(|_| {
+                        let mut sums = [0u64; 256];
+                        sums.iter_mut().for_each(|s| {
+                            sha3::digest::XofReader::read(&mut rand, &mut word);
+                            *s = u64::from_le_bytes(word);
+                        });
+                        sums
+                    })
+                    .collect()
+            })
+            .collect();
+        Self { lookup_table }
+    }
+
+    fn compress(&self, dst: &mut [u8], msg: &[u8]) {
+        (0..self.lookup_table.len()).for_each(|i| {
+            let x = (0..self.lookup_table[i].len()).fold(0u64, |x, j| {
+                x.wrapping_add(self.lookup_table[i][j][msg[j] as usize])
+            });
+            dst[8 * i..8 * i + 8].copy_from_slice(&x.to_le_bytes());
+        });
+    }
+}
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    // Cycling through many messages keeps the benchmark from only touching a few cached table entries.
+    let mut rnd = rand::thread_rng();
+    let msgs: Vec<[u8; 128]> = (0..4096)
+        .map(|_| {
+            let mut msg = [0; 128];
+            rnd.fill(&mut msg[..]);
+            msg
+        })
+        .collect();
+    let mut msg = msgs.iter().cycle();
+    let mut dst = [0; 64];
+
+    let a = Matrix::random_from_seed("Algorand".as_bytes(), 8, 1024);
+    let at = a.lookup_table();
+    let ant = a.nibble_lookup_table();
+    let nested = NestedLookupTable::random(8, 1024);
+    let act = ConstantTimeMatrix::new(a.clone());
+
+    let mut group = c.benchmark_group("compress 128 bytes");
+    group.bench_function("matrix", |b| {
+        b.iter(|| a.compress(black_box(&mut dst), black_box(msg.next().unwrap())))
+    });
+    group.bench_function("lookup table (nested row-major)", |b| {
+        b.iter(|| nested.compress(black_box(&mut dst), black_box(msg.next().unwrap())))
+    });
+    group.bench_function("lookup table (flat column-major)", |b| {
+        b.iter(|| at.compress(black_box(&mut dst), black_box(msg.next().unwrap())))
+    });
+    group.bench_function("nibble lookup table", |b| {
+        b.iter(|| ant.compress(black_box(&mut dst), black_box(msg.next().unwrap())))
+    });
+    group.bench_function("constant-time matrix", |b| {
+        b.iter(|| act.compress(black_box(&mut dst), black_box(msg.next().unwrap())))
+    });
+    group.finish();
+
+    let src: Vec<u8> = msgs.iter().flatten().copied().collect();
+    let mut dst = vec![0; 64 * msgs.len()];
+    let mut group = c.benchmark_group("compress 4096 x 128 bytes");
+    group.bench_function("lookup table (one by one)", |b| {
+        b.iter(|| {
+            dst.chunks_exact_mut(64)
+                .zip(src.chunks_exact(128))
+                .for_each(|(dst, msg)| at.compress(black_box(dst), black_box(msg)))
+        })
+    });
+    group.bench_function("lookup table (batch)", |b| {
+        b.iter(|| at.compress_batch(black_box(&mut dst), black_box(&src)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
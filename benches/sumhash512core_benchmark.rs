@@ -3,6 +3,7 @@ use digest::Update;
 use digest::{core_api::CoreWrapper, FixedOutput};
 use rand::Rng;
 use sumhash::sumhash512core::AlgorandSumhash512Core;
+use sumhash::tree::sumhash512_tree;
 
 pub fn criterion_benchmark(c: &mut Criterion) {
     let mut rnd = rand::thread_rng();
@@ -18,6 +19,40 @@ pub fn criterion_benchmark(c: &mut Criterion) {
             cw.finalize_fixed();
         })
     });
+
+    let leaves: Vec<[u8; 64]> = (0..4096)
+        .map(|_| {
+            let mut leaf = [0; 64];
+            rnd.fill(&mut leaf[..]);
+            leaf
+        })
+        .collect();
+    let mut group = c.benchmark_group("hash 4096 x 64 bytes");
+    group.bench_function("one by one", |b| {
+        b.iter(|| {
+            leaves.iter().for_each(|leaf| {
+                let mut cw = CoreWrapper::<AlgorandSumhash512Core>::default();
+                cw.update(leaf);
+                cw.finalize_fixed();
+            })
+        })
+    });
+    group.bench_function("hash_many", |b| {
+        b.iter(|| AlgorandSumhash512Core::default().hash_many(&leaves))
+    });
+    group.finish();
+
+    let mut data = vec![0; 16 << 20];
+    rnd.fill(&mut data[..]);
+    let mut group = c.benchmark_group("hash 16 MiB");
+    group.sample_size(10);
+    group.bench_function("sumhash512", |b| b.iter(|| sumhash::sumhash512(&data)));
+    group.bench_function("tree", |b| b.iter(|| sumhash512_tree(&data)));
+    #[cfg(feature = "rayon")]
+    group.bench_function("tree parallel", |b| {
+        b.iter(|| sumhash::tree::sumhash512_tree_parallel(&data))
+    });
+    group.finish();
 }
 
 criterion_group!(benches, criterion_benchmark);
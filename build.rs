@@ -0,0 +1,66 @@
+//! Generates the Algorand lookup table when the `embedded-table` feature is enabled.
+//!
+//! This mirrors `Matrix::random_from_seed("Algorand", 8, 1024).lookup_table()` since the build script
+//! can't depend on the crate it builds.
+use sha3::{
+    digest::{ExtendableOutput, Update, XofReader},
+    Shake256,
+};
+use std::{env, fs, path::Path};
+
+const SEED: &[u8] = b"Algorand";
+const N: usize = 8;
+const M: usize = 1024;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    if env::var_os("CARGO_FEATURE_EMBEDDED_TABLE").is_none() {
+        return;
+    }
+
+    let big_endian = env::var("CARGO_CFG_TARGET_ENDIAN").unwrap() == "big";
+    let out_dir = env::var_os("OUT_DIR").unwrap();
+
+    let mut xof = Shake256::default();
+    xof.update(&64u16.to_le_bytes());
+    xof.update(&(N as u16).to_le_bytes());
+    xof.update(&(M as u16).to_le_bytes());
+    xof.update(SEED);
+    let mut rand = xof.finalize_xof();
+    let matrix: Vec<u64> = (0..N * M)
+        .map(|_| {
+            let mut word = [0; 8];
+            rand.read(&mut word);
+            u64::from_le_bytes(word)
+        })
+        .collect();
+
+    // The table uses the LookupTable layout, [m/8][256][n], and is stored in the target byte order
+    // so it can be embedded as a [u64] array.
+    let mut table = Vec::with_capacity(M / 8 * 256 * N * 8);
+    for j in (0..M).step_by(8) {
+        for b in 0..256usize {
+            for i in 0..N {
+                let sum = (0..8)
+                    .filter(|k| (b >> k) & 1 == 1)
+                    .fold(0u64, |sum, k| sum.wrapping_add(matrix[i * M + j + k]));
+                if big_endian {
+                    table.extend_from_slice(&sum.to_be_bytes());
+                } else {
+                    table.extend_from_slice(&sum.to_le_bytes());
+                }
+            }
+        }
+    }
+    fs::write(Path::new(&out_dir).join("algorand_lookup_table.bin"), table).unwrap();
+
+    let mut fingerprint = [0; 32];
+    let mut xof = Shake256::default();
+    xof.update(SEED);
+    xof.finalize_xof().read(&mut fingerprint);
+    fs::write(
+        Path::new(&out_dir).join("algorand_seed_fingerprint.bin"),
+        fingerprint,
+    )
+    .unwrap();
+}
@@ -0,0 +1,303 @@
+//! sumhash inspects and exports sumhash instances, e.g. to debug them against go-sumhash.
+//!
+//! Matrices and lookup tables are written in one of three formats:
+//! - `bin` is the checksummed binary encoding documented in the `compress::encoding` module.
+//! - `hex` is the same encoding in hex, on a single line.
+//! - `json` is an object with the dimensions `n` and `m`, the `seed_fingerprint` in hex, and the elements
+//!   as exact u64 numbers, `matrix` being `[n][m]` and `lookup_table` being `[n][m/8][256]`, i.e. row by
+//!   row unlike the column-major binary encoding.
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use sumhash::compress::{Compressor, LookupTable, Matrix, SeedFingerprint};
+
+/// Inspect and export sumhash instances.
+#[derive(Parser)]
+#[command(name = "sumhash", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Write the matrix generated from a seed.
+    Matrix {
+        #[command(flatten)]
+        instance: Instance,
+        #[command(flatten)]
+        output: Output,
+    },
+    /// Write the lookup table of the matrix generated from a seed.
+    Table {
+        #[command(flatten)]
+        instance: Instance,
+        #[command(flatten)]
+        output: Output,
+    },
+    /// Compress a single message and print the output in hex.
+    Compress {
+        #[command(flatten)]
+        instance: Instance,
+        /// The compressor to use, all of them giving the same output.
+        #[arg(long, value_enum, default_value_t = Kind::Table)]
+        compressor: Kind,
+        /// The message of m/8 bytes, in hex.
+        message: String,
+    },
+}
+
+// Instance selects the matrix of a sumhash instance, the Algorand one by default.
+#[derive(Args)]
+struct Instance {
+    /// The seed of the matrix, as text.
+    #[arg(long, default_value = "Algorand", conflicts_with = "seed_hex")]
+    seed: String,
+    /// The seed of the matrix, in hex.
+    #[arg(long, value_name = "HEX")]
+    seed_hex: Option<String>,
+    /// The number of rows of the matrix.
+    #[arg(short, long, default_value_t = 8)]
+    n: usize,
+    /// The number of columns of the matrix.
+    #[arg(short, long, default_value_t = 1024)]
+    m: usize,
+}
+
+impl Instance {
+    // matrix generates the matrix of the instance.
+    fn matrix(&self) -> Result<Matrix> {
+        let seed = match &self.seed_hex {
+            Some(seed) => hex::decode(seed).context("invalid seed")?,
+            None => self.seed.as_bytes().to_vec(),
+        };
+        Ok(Matrix::try_random_from_seed(&seed, self.n, self.m)?)
+    }
+}
+
+#[derive(Args)]
+struct Output {
+    /// The output format.
+    #[arg(long, value_enum, default_value_t = Format::Json)]
+    format: Format,
+    /// The file to write to, instead of the standard output.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    /// The checksummed binary encoding.
+    Bin,
+    /// The binary encoding in hex.
+    Hex,
+    /// A JSON object.
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Kind {
+    /// The matrix itself.
+    Matrix,
+    /// The byte lookup table.
+    Table,
+    /// The nibble lookup table.
+    Nibble,
+}
+
+fn main() -> Result<()> {
+    match Cli::parse().command {
+        Command::Matrix { instance, output } => output.write(&instance.matrix()?),
+        Command::Table { instance, output } => output.write(&instance.matrix()?.lookup_table()),
+        Command::Compress {
+            instance,
+            compressor,
+            message,
+        } => {
+            let message = hex::decode(message).context("invalid message")?;
+            let digest = compress(&instance.matrix()?, compressor, &message)?;
+            println!("{}", hex::encode(digest));
+            Ok(())
+        }
+    }
+}
+
+// compress compresses msg with the compressor of the given kind built from a.
+fn compress(a: &Matrix, kind: Kind, msg: &[u8]) -> Result<Vec<u8>> {
+    let mut dst = vec![0; a.output_len()];
+    match kind {
+        Kind::Matrix => a.try_compress(&mut dst, msg)?,
+        Kind::Table => a.try_lookup_table()?.try_compress(&mut dst, msg)?,
+        Kind::Nibble => a.try_nibble_lookup_table()?.try_compress(&mut dst, msg)?,
+    }
+    Ok(dst)
+}
+
+// Export is an instance that can be written in every format.
+trait Export {
+    fn write_bin(&self, w: &mut dyn Write) -> Result<(), sumhash::Error>;
+    fn write_json(&self, w: &mut dyn Write) -> io::Result<()>;
+}
+
+impl Export for Matrix {
+    fn write_bin(&self, w: &mut dyn Write) -> Result<(), sumhash::Error> {
+        self.write_to(w)
+    }
+
+    fn write_json(&self, w: &mut dyn Write) -> io::Result<()> {
+        write_json_header(w, self.n(), self.m(), self.seed_fingerprint())?;
+        write!(w, ",\"matrix\":[")?;
+        for i in 0..self.n() {
+            if i > 0 {
+                write!(w, ",")?;
+            }
+            write_json_words(w, self.row(i))?;
+        }
+        writeln!(w, "]}}")
+    }
+}
+
+impl Export for LookupTable<'_> {
+    fn write_bin(&self, w: &mut dyn Write) -> Result<(), sumhash::Error> {
+        self.write_to(w)
+    }
+
+    fn write_json(&self, w: &mut dyn Write) -> io::Result<()> {
+        write_json_header(w, self.n(), self.input_len() * 8, self.seed_fingerprint())?;
+        write!(w, ",\"lookup_table\":[")?;
+        for i in 0..self.n() {
+            write!(w, "{}[", if i > 0 { "," } else { "" })?;
+            for j in 0..self.input_len() {
+                if j > 0 {
+                    write!(w, ",")?;
+                }
+                let sums: Vec<u64> = (0..=255).map(|b| self.sum(i, j, b)).collect();
+                write_json_words(w, &sums)?;
+            }
+            write!(w, "]")?;
+        }
+        writeln!(w, "]}}")
+    }
+}
+
+// write_json_header opens the JSON object of an instance and writes its dimensions and seed fingerprint.
+fn write_json_header(
+    w: &mut dyn Write,
+    n: usize,
+    m: usize,
+    seed_fingerprint: Option<&SeedFingerprint>,
+) -> io::Result<()> {
+    let seed_fingerprint = match seed_fingerprint {
+        Some(fp) => format!("\"{}\"", hex::encode(fp)),
+        None => "null".to_string(),
+    };
+    write!(
+        w,
+        "{{\"n\":{},\"m\":{},\"seed_fingerprint\":{}",
+        n, m, seed_fingerprint
+    )
+}
+
+// write_json_words writes words as a JSON array.
+fn write_json_words(w: &mut dyn Write, words: &[u64]) -> io::Result<()> {
+    write!(w, "[")?;
+    for (k, word) in words.iter().enumerate() {
+        write!(w, "{}{}", if k > 0 { "," } else { "" }, word)?;
+    }
+    write!(w, "]")
+}
+
+impl Output {
+    // write writes the instance to the output file or the standard output.
+    fn write(&self, instance: &dyn Export) -> Result<()> {
+        let w: Box<dyn Write> = match &self.output {
+            Some(path) => Box::new(
+                File::create(path).with_context(|| format!("creating {}", path.display()))?,
+            ),
+            None => Box::new(io::stdout().lock()),
+        };
+        let mut w = BufWriter::new(w);
+        export(instance, self.format, &mut w)?;
+        w.flush()?;
+        Ok(())
+    }
+}
+
+// export writes the instance to w in the given format.
+fn export(instance: &dyn Export, format: Format, w: &mut dyn Write) -> Result<()> {
+    match format {
+        Format::Bin => instance.write_bin(w)?,
+        Format::Hex => {
+            let mut bin = Vec::new();
+            instance.write_bin(&mut bin)?;
+            writeln!(w, "{}", hex::encode(bin))?;
+        }
+        Format::Json => instance.write_json(w)?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn exported(instance: &dyn Export, format: Format) -> Vec<u8> {
+        let mut out = Vec::new();
+        export(instance, format, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn formats() {
+        let a = Matrix::random_from_seed(b"seed", 2, 16);
+        let fp = hex::encode(a.seed_fingerprint().unwrap());
+        let t = a.lookup_table();
+
+        let bin = exported(&a, Format::Bin);
+        assert_eq!(Matrix::read_from(&bin[..]).unwrap().row(1), a.row(1));
+        let hex = exported(&a, Format::Hex);
+        assert_eq!(hex, format!("{}\n", hex::encode(&bin)).into_bytes());
+
+        let json = String::from_utf8(exported(&a, Format::Json)).unwrap();
+        let rows: Vec<String> = (0..2)
+            .map(|i| {
+                let row: Vec<String> = a.row(i).iter().map(u64::to_string).collect();
+                format!("[{}]", row.join(","))
+            })
+            .collect();
+        let want = format!(
+            "{{\"n\":2,\"m\":16,\"seed_fingerprint\":\"{}\",\"matrix\":[{}]}}\n",
+            fp,
+            rows.join(",")
+        );
+        assert_eq!(json, want);
+
+        let bin = exported(&t, Format::Bin);
+        assert_eq!(
+            LookupTable::read_from(&bin[..]).unwrap().sum(1, 1, 0xff),
+            t.sum(1, 1, 0xff)
+        );
+        let json = String::from_utf8(exported(&t, Format::Json)).unwrap();
+        let prefix = format!(
+            "{{\"n\":2,\"m\":16,\"seed_fingerprint\":\"{}\",\"lookup_table\":[[[0,{},{},",
+            fp,
+            a.row(0)[0],
+            a.row(0)[1]
+        );
+        assert!(json.starts_with(&prefix));
+        assert_eq!(json.matches(',').count(), 3 + 2 * 2 * 256 - 1);
+    }
+
+    #[test]
+    fn compressors() {
+        let a = Matrix::random_from_seed(b"Algorand", 8, 1024);
+        let msg: Vec<u8> = (0..128).collect();
+        let want = compress(&a, Kind::Matrix, &msg).unwrap();
+        assert_eq!(compress(&a, Kind::Table, &msg).unwrap(), want);
+        assert_eq!(compress(&a, Kind::Nibble, &msg).unwrap(), want);
+        assert!(compress(&a, Kind::Table, &msg[1..]).is_err());
+    }
+}
@@ -0,0 +1,237 @@
+//! sumhash512sum prints or checks Sumhash512 checksums, like sha256sum.
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::process::ExitCode;
+
+use clap::Parser;
+use sumhash::io::HashingWriter;
+use sumhash::sumhash512core::{DIGEST_BLOCK_SIZE, DIGEST_SIZE};
+use sumhash::{Sumhash512, Sumhash512Salted};
+
+// TAG names the algorithm in BSD style checksum lines.
+const TAG: &str = "Sumhash512";
+
+/// Print or check Sumhash512 checksums.
+#[derive(Parser)]
+#[command(name = "sumhash512sum", version)]
+struct Args {
+    /// Files to hash, or to read checksums from with --check. Standard input is read when there is
+    /// no file, or when a file is -.
+    files: Vec<String>,
+    /// Read checksums from the files and check them.
+    #[arg(short, long)]
+    check: bool,
+    /// Salt the hash with the 64 bytes given in hex.
+    #[arg(long, value_name = "HEX", value_parser = parse_salt)]
+    salt: Option<[u8; DIGEST_BLOCK_SIZE]>,
+    /// Print BSD style checksums.
+    #[arg(long, conflicts_with = "check")]
+    tag: bool,
+}
+
+fn main() -> ExitCode {
+    let mut args = Args::parse();
+    if args.files.is_empty() {
+        args.files.push("-".to_string());
+    }
+
+    let ok = if args.check {
+        check_files(&args.files, args.salt)
+    } else {
+        hash_files(&args.files, args.salt, args.tag)
+    };
+    if ok {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn parse_salt(s: &str) -> Result<[u8; DIGEST_BLOCK_SIZE], String> {
+    let salt = hex::decode(s).map_err(|err| err.to_string())?;
+    salt.try_into().map_err(|salt: Vec<u8>| {
+        format!(
+            "salt is {} bytes long, expected {}",
+            salt.len(),
+            DIGEST_BLOCK_SIZE
+        )
+    })
+}
+
+// open returns a reader of the named file, or of the standard input for -.
+fn open(name: &str) -> io::Result<Box<dyn Read>> {
+    if name == "-" {
+        Ok(Box::new(io::stdin().lock()))
+    } else {
+        Ok(Box::new(File::open(name)?))
+    }
+}
+
+// digest returns the digest of everything read from r, salted if a salt is given.
+fn digest(
+    salt: Option<[u8; DIGEST_BLOCK_SIZE]>,
+    mut r: impl Read,
+) -> io::Result<[u8; DIGEST_SIZE]> {
+    let h = salt.map_or_else(Sumhash512::default, Sumhash512Salted);
+    let mut w = HashingWriter::with_hasher(io::sink(), h);
+    io::copy(&mut r, &mut w)?;
+    Ok(w.finalize())
+}
+
+// parse_line returns the digest and the file name of a checksum line, either in the `digest  name`
+// format or in the BSD `Sumhash512 (name) = digest` format.
+fn parse_line(line: &str) -> Option<([u8; DIGEST_SIZE], &str)> {
+    let (digest, name) = match line.strip_prefix(TAG).and_then(|l| l.strip_prefix(" (")) {
+        Some(rest) => {
+            let (name, digest) = rest.rsplit_once(") = ")?;
+            (digest, name)
+        }
+        None => {
+            let (digest, rest) = line.split_once(' ')?;
+            // The file name is preceded by a space in text mode, and by * in binary mode.
+            let name = rest.strip_prefix(' ').or_else(|| rest.strip_prefix('*'))?;
+            (digest, name)
+        }
+    };
+    if name.is_empty() {
+        return None;
+    }
+    let digest = hex::decode(digest).ok()?.try_into().ok()?;
+    Some((digest, name))
+}
+
+fn hash_files(files: &[String], salt: Option<[u8; DIGEST_BLOCK_SIZE]>, tag: bool) -> bool {
+    let mut ok = true;
+    for name in files {
+        match open(name).and_then(|r| digest(salt, r)) {
+            Ok(d) if tag => println!("{} ({}) = {}", TAG, name, hex::encode(d)),
+            Ok(d) => println!("{}  {}", hex::encode(d), name),
+            Err(err) => {
+                eprintln!("sumhash512sum: {}: {}", name, err);
+                ok = false;
+            }
+        }
+    }
+    ok
+}
+
+fn check_files(files: &[String], salt: Option<[u8; DIGEST_BLOCK_SIZE]>) -> bool {
+    let mut ok = true;
+    let (mut malformed, mut unreadable, mut mismatched) = (0, 0, 0);
+    for list in files {
+        let r = match open(list) {
+            Ok(r) => BufReader::new(r),
+            Err(err) => {
+                eprintln!("sumhash512sum: {}: {}", list, err);
+                ok = false;
+                continue;
+            }
+        };
+
+        let mut checked = 0;
+        for line in r.lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(err) => {
+                    eprintln!("sumhash512sum: {}: {}", list, err);
+                    ok = false;
+                    break;
+                }
+            };
+            let (want, name) = match parse_line(&line) {
+                Some(parsed) => parsed,
+                None => {
+                    malformed += 1;
+                    continue;
+                }
+            };
+
+            checked += 1;
+            match open(name).and_then(|r| digest(salt, r)) {
+                Ok(got) if got == want => println!("{}: OK", name),
+                Ok(_) => {
+                    println!("{}: FAILED", name);
+                    mismatched += 1;
+                }
+                Err(err) => {
+                    eprintln!("sumhash512sum: {}: {}", name, err);
+                    println!("{}: FAILED open or read", name);
+                    unreadable += 1;
+                }
+            }
+        }
+        if checked == 0 {
+            eprintln!(
+                "sumhash512sum: {}: no properly formatted checksum lines found",
+                list
+            );
+            ok = false;
+        }
+    }
+
+    warn(
+        malformed,
+        "line is improperly formatted",
+        "lines are improperly formatted",
+    );
+    warn(
+        unreadable,
+        "listed file could not be read",
+        "listed files could not be read",
+    );
+    warn(
+        mismatched,
+        "computed checksum did NOT match",
+        "computed checksums did NOT match",
+    );
+    ok && unreadable == 0 && mismatched == 0
+}
+
+// warn prints a warning about count problems, if there are any.
+fn warn(count: usize, singular: &str, plural: &str) {
+    match count {
+        0 => {}
+        1 => eprintln!("sumhash512sum: WARNING: 1 {}", singular),
+        _ => eprintln!("sumhash512sum: WARNING: {} {}", count, plural),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sumhash::{sumhash512, sumhash512_salted};
+
+    #[test]
+    fn parse() {
+        let d = sumhash512("hello world");
+        let h = hex::encode(d);
+        assert_eq!(parse_line(&format!("{}  a b", h)), Some((d, "a b")));
+        assert_eq!(parse_line(&format!("{} *a", h)), Some((d, "a")));
+        let upper = h.to_uppercase();
+        assert_eq!(parse_line(&format!("{}  a", upper)), Some((d, "a")));
+        assert_eq!(
+            parse_line(&format!("Sumhash512 (a) = b) = {}", h)),
+            Some((d, "a) = b"))
+        );
+
+        assert_eq!(parse_line(&format!("{} a", h)), None);
+        assert_eq!(parse_line(&format!("{}  ", h)), None);
+        assert_eq!(parse_line(&format!("{}  a", &h[2..])), None);
+        assert_eq!(parse_line(&format!("SHA256 (a) = {}", h)), None);
+        assert_eq!(parse_line(""), None);
+
+        assert_eq!(parse_salt(&"2a".repeat(64)), Ok([0x2a; 64]));
+        assert!(parse_salt(&"2a".repeat(63)).is_err());
+        assert!(parse_salt("zz").is_err());
+    }
+
+    #[test]
+    fn digest() {
+        let data = [7u8; 1000];
+        assert_eq!(super::digest(None, &data[..]).unwrap(), sumhash512(data));
+        assert_eq!(
+            super::digest(Some([1; 64]), &data[..]).unwrap(),
+            sumhash512_salted([1; 64], data)
+        );
+    }
+}
@@ -1,57 +1,299 @@
 use byteorder::ReadBytesExt;
+use once_cell::sync::Lazy;
 use sha3::{digest::ExtendableOutput, Shake256};
-use std::io::Write;
+use std::borrow::Cow;
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+use crate::Error;
+
+mod ct;
+pub mod encoding;
+#[cfg(target_arch = "x86_64")]
+mod x86;
+
+pub use ct::ConstantTimeMatrix;
+
+/// SeedFingerprint identifies the seed a matrix was generated from, without revealing it.
+pub type SeedFingerprint = [u8; 32];
 
 /// Matrix is the n-by-m sumhash matrix A with elements in Z_q where q=2^64.
+/// Its elements are stored row by row in a single allocation, so element (i, j) is at index `i * m + j`.
 #[derive(Clone)]
 pub struct Matrix {
-    matrix: Vec<Vec<u64>>,
+    matrix: Vec<u64>,
+    n: usize,
+    seed_fingerprint: Option<SeedFingerprint>,
 }
 
 impl Matrix {
     /// random_matrix generates a random n x m matrix from the random source.
-    pub fn random_matrix<T: ReadBytesExt>(mut rand: T, n: usize, m: usize) -> Matrix {
-        if m % 8 != 0 {
-            panic!("m={:?} is not a multiple of 8", m);
+    /// It panics in the cases where try_random_matrix returns an error.
+    pub fn random_matrix<T: ReadBytesExt>(rand: T, n: usize, m: usize) -> Matrix {
+        Matrix::try_random_matrix(rand, n, m).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// try_random_matrix generates a random n x m matrix from the random source.
+    /// It fails if m isn't a multiple of 8, if the matrix would be empty or if the random source
+    /// can't provide n x m words.
+    pub fn try_random_matrix<T: ReadBytesExt>(
+        mut rand: T,
+        n: usize,
+        m: usize,
+    ) -> Result<Matrix, Error> {
+        if !m.is_multiple_of(8) {
+            return Err(Error::InvalidDimensions { n, m });
+        }
+        if n == 0 || m == 0 {
+            return Err(Error::EmptyMatrix);
         }
 
-        let mut matrix = Vec::with_capacity(n);
-        (0..n).for_each(|i| {
-            matrix.push(Vec::with_capacity(m));
-            (0..m).for_each(|_| {
-                matrix[i].push(rand.read_u64::<byteorder::LittleEndian>().unwrap());
-            });
-        });
-        Matrix { matrix }
+        let mut matrix = Vec::with_capacity(n * m);
+        for _ in 0..n * m {
+            matrix.push(
+                rand.read_u64::<byteorder::LittleEndian>()
+                    .map_err(Error::ShortRandomSource)?,
+            );
+        }
+        Ok(Matrix {
+            matrix,
+            n,
+            seed_fingerprint: None,
+        })
     }
+
     /// random_matrix_from_seed creates a random-looking matrix to be used for the sumhash function using the seed bytes.
     /// n and m are the rows and columns of the matrix respectively.
+    /// It panics in the cases where try_random_from_seed returns an error.
     pub fn random_from_seed(seed: &[u8], n: usize, m: usize) -> Self {
+        Matrix::try_random_from_seed(seed, n, m).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// try_random_from_seed creates a random-looking matrix to be used for the sumhash function using the seed bytes.
+    /// n and m are the rows and columns of the matrix respectively, and must fit in 16 bits.
+    pub fn try_random_from_seed(seed: &[u8], n: usize, m: usize) -> Result<Self, Error> {
+        let (n16, m16) = match (u16::try_from(n), u16::try_from(m)) {
+            (Ok(n16), Ok(m16)) => (n16, m16),
+            _ => return Err(Error::InvalidDimensions { n, m }),
+        };
+
         let mut xof = Shake256::default();
         xof.write_all(&64u16.to_le_bytes()).unwrap();
-        xof.write_all(&(n as u16).to_le_bytes()).unwrap();
-        xof.write_all(&(m as u16).to_le_bytes()).unwrap();
+        xof.write_all(&n16.to_le_bytes()).unwrap();
+        xof.write_all(&m16.to_le_bytes()).unwrap();
         xof.write_all(seed).unwrap();
 
-        Matrix::random_matrix(xof.finalize_xof(), n, m)
+        let mut a = Matrix::try_random_matrix(xof.finalize_xof(), n, m)?;
+        a.seed_fingerprint = Some(seed_fingerprint(seed));
+        Ok(a)
+    }
+
+    /// seed_fingerprint returns the fingerprint of the seed the matrix was generated from, if any.
+    pub fn seed_fingerprint(&self) -> Option<&SeedFingerprint> {
+        self.seed_fingerprint.as_ref()
+    }
+
+    /// n returns the number of rows of the matrix.
+    pub fn n(&self) -> usize {
+        self.n
+    }
+
+    /// m returns the number of columns of the matrix.
+    pub fn m(&self) -> usize {
+        self.matrix.len() / self.n
+    }
+
+    /// row returns the m elements of the i-th row.
+    /// It panics if i isn't smaller than n.
+    pub fn row(&self, i: usize) -> &[u64] {
+        let m = self.m();
+        &self.matrix[i * m..(i + 1) * m]
+    }
+
+    /// element returns the element at row i and column j.
+    /// It panics if i isn't smaller than n or j isn't smaller than m.
+    pub fn element(&self, i: usize, j: usize) -> u64 {
+        self.row(i)[j]
+    }
+
+    /// column returns the n elements of the j-th column.
+    /// It panics if j isn't smaller than m.
+    pub fn column(&self, j: usize) -> Vec<u64> {
+        (0..self.n).map(|i| self.element(i, j)).collect()
+    }
+
+    /// mul_bits returns the product A·x in Z_q^n of the matrix with a vector x of m bits, i.e. the sum of the
+    /// columns selected by x.
+    /// Compressing a message is mul_bits of its bits, taken from the least significant bit of each byte, the output
+    /// being the little-endian encoding of the product. As the product is linear, `A·x + A·y = A·(x+y)` when the
+    /// bits of x and y are disjoint.
+    /// It panics in the cases where try_mul_bits returns an error.
+    pub fn mul_bits(&self, x: &[bool]) -> Vec<u64> {
+        self.try_mul_bits(x).unwrap_or_else(|e| panic!("{}", e))
     }
+
+    /// try_mul_bits returns the product A·x in Z_q^n of the matrix with a vector x of m bits.
+    /// It fails if x doesn't have m elements.
+    pub fn try_mul_bits(&self, x: &[bool]) -> Result<Vec<u64>, Error> {
+        self.check_vector_length(x.len())?;
+        Ok((0..self.n)
+            .map(|i| {
+                self.row(i).iter().zip(x).fold(0u64, |s, (&a, &b)| {
+                    s.wrapping_add(a & u64::from(b).wrapping_neg())
+                })
+            })
+            .collect())
+    }
+
+    /// mul_vec returns the product A·x in Z_q^n of the matrix with a vector x of Z_q^m.
+    /// It panics in the cases where try_mul_vec returns an error.
+    pub fn mul_vec(&self, x: &[u64]) -> Vec<u64> {
+        self.try_mul_vec(x).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// try_mul_vec returns the product A·x in Z_q^n of the matrix with a vector x of Z_q^m.
+    /// It fails if x doesn't have m elements.
+    pub fn try_mul_vec(&self, x: &[u64]) -> Result<Vec<u64>, Error> {
+        self.check_vector_length(x.len())?;
+        Ok((0..self.n)
+            .map(|i| {
+                self.row(i)
+                    .iter()
+                    .zip(x)
+                    .fold(0u64, |s, (&a, &b)| s.wrapping_add(a.wrapping_mul(b)))
+            })
+            .collect())
+    }
+
+    // check_vector_length validates the length of a vector multiplied by the matrix.
+    fn check_vector_length(&self, len: usize) -> Result<(), Error> {
+        if len != self.m() {
+            return Err(Error::VectorLength {
+                got: len,
+                expected: self.m(),
+            });
+        }
+        Ok(())
+    }
+
     /// lookup_table generates a lookuptable used to increase hash calculation performance.
-    pub fn lookup_table(&self) -> LookupTable {
-        let n = self.matrix.len();
-        let m = self.matrix[0].len();
+    /// It panics in the cases where try_lookup_table returns an error.
+    pub fn lookup_table(&self) -> LookupTable<'static> {
+        self.try_lookup_table().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// try_lookup_table generates a lookuptable used to increase hash calculation performance.
+    /// It fails if the matrix is empty or its number of columns isn't a multiple of 8.
+    pub fn try_lookup_table(&self) -> Result<LookupTable<'static>, Error> {
+        let (n, m) = self.table_dimensions()?;
 
-        let mut at = Vec::with_capacity(n);
+        let mut at = vec![0u64; m / 8 * 256 * n];
         (0..n).for_each(|i| {
-            at.push(Vec::with_capacity(m / 8));
-            (0..m).step_by(8).for_each(|j| {
-                at[i].push([0u64; 256]);
-                (0..256)
-                    .for_each(|b| at[i][j / 8][b] = sum_bits(&self.matrix[i][j..j + 8], b as u8));
+            let row = self.row(i);
+            (0..m / 8).for_each(|j| {
+                (0..256).for_each(|b| {
+                    at[LookupTable::index(n, j, b, i)] = sum_bits(&row[8 * j..8 * (j + 1)], b as u8)
+                });
             });
         });
 
-        LookupTable { lookup_table: at }
+        Ok(LookupTable {
+            lookup_table: Cow::Owned(at),
+            n,
+            seed_fingerprint: self.seed_fingerprint,
+        })
+    }
+
+    /// nibble_lookup_table generates a lookup table indexed by nibbles instead of bytes, which is 16 times smaller
+    /// than the one from lookup_table.
+    /// It panics in the cases where try_nibble_lookup_table returns an error.
+    pub fn nibble_lookup_table(&self) -> NibbleLookupTable {
+        self.try_nibble_lookup_table()
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// try_nibble_lookup_table generates a lookup table indexed by nibbles instead of bytes, which is 16 times smaller
+    /// than the one from try_lookup_table.
+    /// It fails if the matrix is empty or its number of columns isn't a multiple of 8.
+    pub fn try_nibble_lookup_table(&self) -> Result<NibbleLookupTable, Error> {
+        let (n, m) = self.table_dimensions()?;
+
+        let mut at = vec![0u64; m / 4 * 16 * n];
+        (0..n).for_each(|i| {
+            let row = self.row(i);
+            (0..m / 4).for_each(|p| {
+                (0..16).for_each(|b| {
+                    at[NibbleLookupTable::index(n, p, b, i)] =
+                        sum_bits(&row[4 * p..4 * (p + 1)], b as u8)
+                });
+            });
+        });
+
+        Ok(NibbleLookupTable {
+            lookup_table: at,
+            n,
+            seed_fingerprint: self.seed_fingerprint,
+        })
+    }
+
+    // table_dimensions returns the dimensions of the matrix, checking a lookup table can be built from it.
+    fn table_dimensions(&self) -> Result<(usize, usize), Error> {
+        let n = self.n;
+        let m = if n == 0 { 0 } else { self.m() };
+        if n == 0 || m == 0 {
+            return Err(Error::EmptyMatrix);
+        }
+        if !m.is_multiple_of(8) {
+            return Err(Error::InvalidDimensions { n, m });
+        }
+        Ok((n, m))
+    }
+}
+
+// seed_fingerprint hashes the seed with Shake256.
+fn seed_fingerprint(seed: &[u8]) -> SeedFingerprint {
+    let mut xof = Shake256::default();
+    xof.write_all(seed).unwrap();
+    let mut fp = [0; 32];
+    xof.finalize_xof().read_exact(&mut fp).unwrap();
+    fp
+}
+
+/// add returns the sum x + y of two vectors of Z_q, e.g. two products of a matrix.
+/// It panics in the cases where try_add returns an error.
+pub fn add(x: &[u64], y: &[u64]) -> Vec<u64> {
+    try_add(x, y).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// try_add returns the sum x + y of two vectors of Z_q, e.g. two products of a matrix.
+/// It fails if y doesn't have the length of x.
+pub fn try_add(x: &[u64], y: &[u64]) -> Result<Vec<u64>, Error> {
+    check_same_length(x, y)?;
+    Ok(x.iter().zip(y).map(|(x, y)| x.wrapping_add(*y)).collect())
+}
+
+/// sub returns the difference x - y of two vectors of Z_q, e.g. two products of a matrix.
+/// It panics in the cases where try_sub returns an error.
+pub fn sub(x: &[u64], y: &[u64]) -> Vec<u64> {
+    try_sub(x, y).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// try_sub returns the difference x - y of two vectors of Z_q, e.g. two products of a matrix.
+/// It fails if y doesn't have the length of x.
+pub fn try_sub(x: &[u64], y: &[u64]) -> Result<Vec<u64>, Error> {
+    check_same_length(x, y)?;
+    Ok(x.iter().zip(y).map(|(x, y)| x.wrapping_sub(*y)).collect())
+}
+
+// check_same_length validates that y has the length of x.
+fn check_same_length(x: &[u64], y: &[u64]) -> Result<(), Error> {
+    if y.len() != x.len() {
+        return Err(Error::VectorLength {
+            got: y.len(),
+            expected: x.len(),
+        });
     }
+    Ok(())
 }
 
 #[inline(always)]
@@ -62,92 +304,461 @@ fn sum_bits(a: &[u64], b: u8) -> u64 {
 }
 
 /// LookupTable is the precomputed sums from a matrix for every possible byte of input.
-/// Its dimensions are `[n][m/8][256]u64`.
+/// Its dimensions are `[m/8][256][n]u64`.
+///
+/// The sums are stored in a single allocation in column-major order: the sum of the bits of byte value b
+/// over the columns of byte position j in row i is at index `(j * 256 + b) * n + i`. The n sums needed for
+/// one input byte are thus contiguous, and compression walks the input once for all rows.
+///
+/// The sums are either owned by the table or borrowed for 'a, e.g. from a memory-mapped file decoded with
+/// [`LookupTable::from_bytes`].
+#[derive(Clone)]
+pub struct LookupTable<'a> {
+    lookup_table: Cow<'a, [u64]>,
+    n: usize,
+    seed_fingerprint: Option<SeedFingerprint>,
+}
+
+impl<'a> LookupTable<'a> {
+    // borrowed returns a lookup table of n rows borrowing the precomputed sums in lookup_table.
+    pub(crate) const fn borrowed(
+        lookup_table: &'a [u64],
+        n: usize,
+        seed_fingerprint: Option<SeedFingerprint>,
+    ) -> Self {
+        Self {
+            lookup_table: Cow::Borrowed(lookup_table),
+            n,
+            seed_fingerprint,
+        }
+    }
+
+    // index returns the position of the sum for row i, byte position j and byte value b in a table of n rows.
+    #[inline(always)]
+    fn index(n: usize, j: usize, b: usize, i: usize) -> usize {
+        (j * 256 + b) * n + i
+    }
+
+    /// n returns the number of rows of the matrix the table was built from.
+    pub fn n(&self) -> usize {
+        self.n
+    }
+
+    /// sum returns the sum of the elements of row i over the columns of byte position j selected by the bits of b.
+    /// It panics if i isn't smaller than n or j isn't smaller than m/8.
+    pub fn sum(&self, i: usize, j: usize, b: u8) -> u64 {
+        assert!(i < self.n, "row {} is out of range, n is {}", i, self.n);
+        self.lookup_table[Self::index(self.n, j, b as usize, i)]
+    }
+
+    /// seed_fingerprint returns the fingerprint of the seed of the matrix the table was built from, if any.
+    pub fn seed_fingerprint(&self) -> Option<&SeedFingerprint> {
+        self.seed_fingerprint.as_ref()
+    }
+
+    /// into_owned returns the table with its sums copied if they're borrowed, so that it outlives them.
+    pub fn into_owned(self) -> LookupTable<'static> {
+        LookupTable {
+            lookup_table: Cow::Owned(self.lookup_table.into_owned()),
+            n: self.n,
+            seed_fingerprint: self.seed_fingerprint,
+        }
+    }
+}
+
+/// NibbleLookupTable is the precomputed sums from a matrix for every possible nibble (4 bits) of input.
+/// Its dimensions are `[m/4][16][n]u64`.
+///
+/// It needs twice as many lookups as a LookupTable to compress a message, but is 16 times smaller: 256 KiB instead
+/// of 2 MiB for the Algorand instance, which fits in the L2 cache of most CPUs.
+/// Nibble p of the input holds the bits 4p to 4p+3 of the message, i.e. the low nibble of byte p/2 when p is
+/// even and its high nibble otherwise. Like LookupTable, its sums are stored in column-major order, the
+/// sum for row i, nibble position p and nibble value b being at index `(p * 16 + b) * n + i`.
 #[derive(Clone)]
-pub struct LookupTable {
-    lookup_table: Vec<Vec<[u64; 256]>>,
+pub struct NibbleLookupTable {
+    lookup_table: Vec<u64>,
+    n: usize,
+    seed_fingerprint: Option<SeedFingerprint>,
+}
+
+impl NibbleLookupTable {
+    // index returns the position of the sum for row i, nibble position p and nibble value b in a table of n rows.
+    #[inline(always)]
+    fn index(n: usize, p: usize, b: usize, i: usize) -> usize {
+        (p * 16 + b) * n + i
+    }
+
+    /// seed_fingerprint returns the fingerprint of the seed of the matrix the table was built from, if any.
+    pub fn seed_fingerprint(&self) -> Option<&SeedFingerprint> {
+        self.seed_fingerprint.as_ref()
+    }
 }
 
 /// Compressor represents the compression function which is performed on a message.
 pub trait Compressor: Clone {
     /// Compress performs the compression algorithm on a message and output into dst.
+    /// It panics in the cases where try_compress returns an error.
     fn compress(&self, dst: &mut [u8], src: &[u8]);
+    /// try_compress performs the compression algorithm on a message and output into dst.
+    /// It fails if src or dst don't have the lengths returned by input_len and output_len.
+    /// The default implementation checks the lengths before calling compress.
+    fn try_compress(&self, dst: &mut [u8], src: &[u8]) -> Result<(), Error> {
+        check_lengths(self, dst, src)?;
+        self.compress(dst, src);
+        Ok(())
+    }
+    /// try_compress_batch compresses the consecutive messages of src into the consecutive outputs of dst.
+    /// It fails if src and dst don't hold the same number of messages of input_len and outputs of output_len bytes.
+    fn try_compress_batch(&self, dst: &mut [u8], src: &[u8]) -> Result<(), Error> {
+        check_batch_lengths(self, dst, src)?;
+        dst.chunks_exact_mut(self.output_len())
+            .zip(src.chunks_exact(self.input_len()))
+            .try_for_each(|(dst, msg)| self.try_compress(dst, msg))
+    }
+    /// compress_batch compresses the consecutive messages of src into the consecutive outputs of dst.
+    /// It panics in the cases where try_compress_batch returns an error.
+    fn compress_batch(&self, dst: &mut [u8], src: &[u8]) {
+        if let Err(e) = self.try_compress_batch(dst, src) {
+            panic!("could not compress messages. {}", e)
+        }
+    }
     /// input_len returns the valid length of a message in bytes.
     fn input_len(&self) -> usize; // len(input)
     /// output_len returns the output len in bytes of the compression function.
     fn output_len(&self) -> usize; // len(dst)
+    /// seed_fingerprint returns the fingerprint of the seed of the compressor's matrix, if it's known.
+    fn seed_fingerprint(&self) -> Option<&SeedFingerprint> {
+        None
+    }
+}
+
+// compress_or_panic is the compress of the compressors implementing try_compress, panicking on its errors.
+fn compress_or_panic<C: Compressor>(c: &C, dst: &mut [u8], msg: &[u8]) {
+    if let Err(e) = c.try_compress(dst, msg) {
+        panic!("could not compress message. {}", e)
+    }
+}
+
+// check_lengths validates the dst and src lengths expected by the compressor c.
+fn check_lengths<C: Compressor>(c: &C, dst: &[u8], src: &[u8]) -> Result<(), Error> {
+    if src.len() != c.input_len() {
+        return Err(Error::InputLength {
+            got: src.len(),
+            expected: c.input_len(),
+        });
+    }
+    if dst.len() != c.output_len() {
+        return Err(Error::OutputLength {
+            got: dst.len(),
+            expected: c.output_len(),
+        });
+    }
+    Ok(())
+}
+
+// check_batch_lengths validates that dst and src hold the same number of outputs and messages of
+// the lengths expected by the compressor c.
+fn check_batch_lengths<C: Compressor>(c: &C, dst: &[u8], src: &[u8]) -> Result<(), Error> {
+    if !src.len().is_multiple_of(c.input_len())
+        || !dst.len().is_multiple_of(c.output_len())
+        || src.len() / c.input_len() != dst.len() / c.output_len()
+    {
+        return Err(Error::BatchLength {
+            src: src.len(),
+            dst: dst.len(),
+        });
+    }
+    Ok(())
 }
 
 impl Compressor for Matrix {
+    fn compress(&self, dst: &mut [u8], msg: &[u8]) {
+        compress_or_panic(self, dst, msg)
+    }
+
     fn input_len(&self) -> usize {
-        self.matrix[0].len() / 8
+        self.m() / 8
     }
 
     fn output_len(&self) -> usize {
-        self.matrix.len() * 8
+        self.n * 8
     }
 
-    fn compress(&self, dst: &mut [u8], msg: &[u8]) {
-        if msg.len() != self.input_len() {
-            panic!(
-                "could not compress message. input size is wrong. size is {:?}, expected {:?}",
-                msg.len(),
-                self.input_len()
-            );
-        }
-        if dst.len() != self.output_len() {
-            panic!(
-                "could not compress message. output size is wrong size is {:?}, expected {:?}",
-                dst.len(),
-                self.output_len()
-            )
-        }
+    fn seed_fingerprint(&self) -> Option<&SeedFingerprint> {
+        Matrix::seed_fingerprint(self)
+    }
+
+    fn try_compress(&self, dst: &mut [u8], msg: &[u8]) -> Result<(), Error> {
+        check_lengths(self, dst, msg)?;
 
-        (0..self.matrix.len()).for_each(|i| {
+        (0..self.n).for_each(|i| {
+            let row = self.row(i);
             let x = (0..msg.len()).fold(0u64, |x, j| {
-                x.wrapping_add(sum_bits(&self.matrix[i][8 * j..8 * (j + 1)], msg[j]))
+                x.wrapping_add(sum_bits(&row[8 * j..8 * (j + 1)], msg[j]))
             });
             dst[8 * i..8 * i + 8].clone_from_slice(&x.to_le_bytes());
-        })
+        });
+        Ok(())
     }
 }
 
-impl Compressor for LookupTable {
+impl Compressor for LookupTable<'_> {
+    fn compress(&self, dst: &mut [u8], msg: &[u8]) {
+        compress_or_panic(self, dst, msg)
+    }
+
     fn input_len(&self) -> usize {
-        self.lookup_table[0].len()
+        self.lookup_table.len() / self.n / 256
     }
 
     fn output_len(&self) -> usize {
-        self.lookup_table.len() * 8
+        self.n * 8
+    }
+
+    fn seed_fingerprint(&self) -> Option<&SeedFingerprint> {
+        LookupTable::seed_fingerprint(self)
+    }
+
+    fn try_compress(&self, dst: &mut [u8], msg: &[u8]) -> Result<(), Error> {
+        check_lengths(self, dst, msg)?;
+        self.compress_with(*SUM8, dst, msg);
+        Ok(())
+    }
+
+    fn try_compress_batch(&self, dst: &mut [u8], src: &[u8]) -> Result<(), Error> {
+        check_batch_lengths(self, dst, src)?;
+
+        // Interleaving independent messages lets the table loads of one message overlap with the others.
+        let (input_len, output_len) = (self.input_len(), self.output_len());
+        let mut dsts = dst.chunks_exact_mut(output_len * BATCH_LANES);
+        let mut srcs = src.chunks_exact(input_len * BATCH_LANES);
+        let sum8x4 = *SUM8X4;
+        (&mut dsts)
+            .zip(&mut srcs)
+            .for_each(|(dst, src)| self.compress_interleaved(sum8x4, dst, src));
+
+        let sum8 = *SUM8;
+        dsts.into_remainder()
+            .chunks_exact_mut(output_len)
+            .zip(srcs.remainder().chunks_exact(input_len))
+            .for_each(|(dst, msg)| self.compress_with(sum8, dst, msg));
+        Ok(())
+    }
+}
+
+// The number of messages compressed together by LookupTable::try_compress_batch.
+const BATCH_LANES: usize = 4;
+
+impl LookupTable<'_> {
+    // compress_with compresses msg into dst, summing groups of 8 rows with sum8 and the remaining rows
+    // with a scalar loop.
+    fn compress_with(&self, sum8: Sum8, dst: &mut [u8], msg: &[u8]) {
+        let n = self.n;
+        (0..n).step_by(8).for_each(|i| {
+            let w = (n - i).min(8);
+            let x = if w == 8 {
+                sum8(&self.lookup_table, n, i, msg)
+            } else {
+                let mut x = [0u64; 8];
+                msg.iter().enumerate().for_each(|(j, &b)| {
+                    let sums = &self.lookup_table[Self::index(n, j, b as usize, i)..][..w];
+                    (0..w).for_each(|r| x[r] = x[r].wrapping_add(sums[r]));
+                });
+                x
+            };
+            (0..w).for_each(|r| {
+                dst[8 * (i + r)..8 * (i + r) + 8].copy_from_slice(&x[r].to_le_bytes())
+            });
+        });
     }
+}
+
+impl LookupTable<'_> {
+    // compress_interleaved compresses BATCH_LANES consecutive messages of src into dst, summing groups
+    // of 8 rows with sum8x4 and the remaining rows with a scalar loop.
+    fn compress_interleaved(&self, sum8x4: Sum8x4, dst: &mut [u8], src: &[u8]) {
+        let n = self.n;
+        let input_len = self.input_len();
+        let output_len = self.output_len();
+        let msgs: [&[u8]; BATCH_LANES] =
+            std::array::from_fn(|l| &src[l * input_len..(l + 1) * input_len]);
+        (0..n).step_by(8).for_each(|i| {
+            let w = (n - i).min(8);
+            let x = if w == 8 {
+                sum8x4(&self.lookup_table, n, i, msgs)
+            } else {
+                let mut x = [[0u64; 8]; BATCH_LANES];
+                (0..input_len).for_each(|j| {
+                    (0..BATCH_LANES).for_each(|l| {
+                        let b = msgs[l][j] as usize;
+                        let sums = &self.lookup_table[Self::index(n, j, b, i)..][..w];
+                        (0..w).for_each(|r| x[l][r] = x[l][r].wrapping_add(sums[r]));
+                    });
+                });
+                x
+            };
+            (0..BATCH_LANES).for_each(|l| {
+                (0..w).for_each(|r| {
+                    let k = l * output_len + 8 * (i + r);
+                    dst[k..k + 8].copy_from_slice(&x[l][r].to_le_bytes())
+                });
+            });
+        });
+    }
+}
+
+// Sum8 returns the sums of the table entries selected by msg for the 8 rows starting at row i,
+// in a lookup table of n rows.
+type Sum8 = fn(table: &[u64], n: usize, i: usize, msg: &[u8]) -> [u64; 8];
+
+// SUM8 is the fastest Sum8 implementation supported by the CPU, detected on first use.
+static SUM8: Lazy<Sum8> = Lazy::new(select_sum8);
+
+// select_sum8 returns the fastest Sum8 implementation supported by the CPU.
+fn select_sum8() -> Sum8 {
+    #[cfg(target_arch = "x86_64")]
+    if let Some(sum8) = x86::sum8() {
+        return sum8;
+    }
+    sum8_scalar
+}
+
+// Sum8x4 is Sum8 for BATCH_LANES messages of the same length at once.
+type Sum8x4 =
+    fn(table: &[u64], n: usize, i: usize, msgs: [&[u8]; BATCH_LANES]) -> [[u64; 8]; BATCH_LANES];
+
+// SUM8X4 is the fastest Sum8x4 implementation supported by the CPU, detected on first use.
+static SUM8X4: Lazy<Sum8x4> = Lazy::new(select_sum8x4);
+
+// select_sum8x4 returns the fastest Sum8x4 implementation supported by the CPU.
+fn select_sum8x4() -> Sum8x4 {
+    #[cfg(target_arch = "x86_64")]
+    if let Some(sum8x4) = x86::sum8x4() {
+        return sum8x4;
+    }
+    sum8x4_scalar
+}
 
+// sum8x4_scalar interleaves the table loads of the messages.
+fn sum8x4_scalar(
+    table: &[u64],
+    n: usize,
+    i: usize,
+    msgs: [&[u8]; BATCH_LANES],
+) -> [[u64; 8]; BATCH_LANES] {
+    let mut x = [[0u64; 8]; BATCH_LANES];
+    (0..msgs[0].len()).for_each(|j| {
+        (0..BATCH_LANES).for_each(|l| {
+            let k = LookupTable::index(n, j, msgs[l][j] as usize, i);
+            let sums: &[u64; 8] = table[k..k + 8].try_into().unwrap();
+            (0..8).for_each(|r| x[l][r] = x[l][r].wrapping_add(sums[r]));
+        });
+    });
+    x
+}
+
+// sum8_scalar keeps the 8 partial sums in registers, which compilers vectorize with the baseline
+// instruction set.
+fn sum8_scalar(table: &[u64], n: usize, i: usize, msg: &[u8]) -> [u64; 8] {
+    let mut x = [0u64; 8];
+    msg.iter().enumerate().for_each(|(j, &b)| {
+        let k = LookupTable::index(n, j, b as usize, i);
+        let sums: &[u64; 8] = table[k..k + 8].try_into().unwrap();
+        (0..8).for_each(|r| x[r] = x[r].wrapping_add(sums[r]));
+    });
+    x
+}
+
+impl Compressor for NibbleLookupTable {
     fn compress(&self, dst: &mut [u8], msg: &[u8]) {
-        if msg.len() != self.input_len() {
-            panic!(
-                "could not compress message. input size is wrong. size is {:?}, expected {:?}",
-                msg.len(),
-                self.input_len()
-            )
-        }
-        if dst.len() != self.output_len() {
-            panic!(
-                "could not compress message. output size is wrong size is {:?}, expected {:?}",
-                dst.len(),
-                self.output_len()
-            )
-        }
+        compress_or_panic(self, dst, msg)
+    }
+
+    fn input_len(&self) -> usize {
+        self.lookup_table.len() / self.n / 16 / 2
+    }
+
+    fn output_len(&self) -> usize {
+        self.n * 8
+    }
+
+    fn seed_fingerprint(&self) -> Option<&SeedFingerprint> {
+        NibbleLookupTable::seed_fingerprint(self)
+    }
 
-        (0..self.lookup_table.len()).for_each(|i| {
-            let x = (0..self.lookup_table[i].len()).fold(0u64, |x, j| {
-                x.wrapping_add(self.lookup_table[i][j][msg[j] as usize])
+    fn try_compress(&self, dst: &mut [u8], msg: &[u8]) -> Result<(), Error> {
+        check_lengths(self, dst, msg)?;
+
+        // Rows are summed in groups of 8, keeping the partial sums in registers.
+        let n = self.n;
+        (0..n).step_by(8).for_each(|i| {
+            let w = (n - i).min(8);
+            let mut x = [0u64; 8];
+            msg.iter().enumerate().for_each(|(j, &b)| {
+                let lo = &self.lookup_table[Self::index(n, 2 * j, (b & 0xf) as usize, i)..][..w];
+                let hi = &self.lookup_table[Self::index(n, 2 * j + 1, (b >> 4) as usize, i)..][..w];
+                (0..w).for_each(|r| x[r] = x[r].wrapping_add(lo[r]).wrapping_add(hi[r]));
+            });
+            (0..w).for_each(|r| {
+                dst[8 * (i + r)..8 * (i + r) + 8].copy_from_slice(&x[r].to_le_bytes())
             });
-            dst[8 * i..8 * i + 8].copy_from_slice(&x.to_le_bytes());
         });
+        Ok(())
+    }
+}
+
+impl<C: Compressor> Compressor for &C {
+    fn input_len(&self) -> usize {
+        (**self).input_len()
+    }
+
+    fn output_len(&self) -> usize {
+        (**self).output_len()
+    }
+
+    fn try_compress(&self, dst: &mut [u8], msg: &[u8]) -> Result<(), Error> {
+        (**self).try_compress(dst, msg)
+    }
+
+    fn compress(&self, dst: &mut [u8], msg: &[u8]) {
+        (**self).compress(dst, msg)
+    }
+
+    fn try_compress_batch(&self, dst: &mut [u8], src: &[u8]) -> Result<(), Error> {
+        (**self).try_compress_batch(dst, src)
+    }
+    fn seed_fingerprint(&self) -> Option<&SeedFingerprint> {
+        (**self).seed_fingerprint()
+    }
+}
+
+impl<C: Compressor> Compressor for Arc<C> {
+    fn input_len(&self) -> usize {
+        (**self).input_len()
+    }
+
+    fn output_len(&self) -> usize {
+        (**self).output_len()
+    }
+
+    fn try_compress(&self, dst: &mut [u8], msg: &[u8]) -> Result<(), Error> {
+        (**self).try_compress(dst, msg)
+    }
+
+    fn compress(&self, dst: &mut [u8], msg: &[u8]) {
+        (**self).compress(dst, msg)
+    }
+
+    fn try_compress_batch(&self, dst: &mut [u8], src: &[u8]) -> Result<(), Error> {
+        (**self).try_compress_batch(dst, src)
+    }
+    fn seed_fingerprint(&self) -> Option<&SeedFingerprint> {
+        (**self).seed_fingerprint()
     }
 }
 
 #[cfg(test)]
-pub mod test {
+mod test {
     use super::*;
     #[test]
     fn compression() {
@@ -157,21 +768,247 @@ pub mod test {
         let rand = &mut Shake256::default().finalize_xof();
         let a = Matrix::random_matrix(rand, N, M);
         let at = a.lookup_table();
+        let ant = a.nibble_lookup_table();
 
         assert_eq!(a.input_len(), M / 8, "unexpected input len (A)");
         assert_eq!(at.input_len(), M / 8, "unexpected input len (At)");
+        assert_eq!(ant.input_len(), M / 8, "unexpected input len (Ant)");
         assert_eq!(a.output_len(), N * 8, "unexpected output len (A)");
         assert_eq!(at.output_len(), N * 8, "unexpected output len (At)");
+        assert_eq!(ant.output_len(), N * 8, "unexpected output len (Ant)");
 
         let mut dst1 = vec![0u8; a.output_len()];
         let mut dst2 = vec![0u8; a.output_len()];
+        let mut dst3 = vec![0u8; a.output_len()];
 
         (0..1000).for_each(|_| {
             let msg: Vec<u8> = (0..a.input_len()).map(|_| rand::random::<u8>()).collect();
             a.compress(&mut dst1, &msg);
             at.compress(&mut dst2, &msg);
+            ant.compress(&mut dst3, &msg);
 
             assert_eq!(dst1, dst2, "matrix and lookup table outputs are different");
+            assert_eq!(
+                dst1, dst3,
+                "matrix and nibble lookup table outputs are different"
+            );
+        });
+    }
+
+    #[test]
+    fn errors() {
+        let rand = &mut Shake256::default().finalize_xof();
+        assert!(matches!(
+            Matrix::try_random_matrix(&mut *rand, 8, 1020),
+            Err(Error::InvalidDimensions { n: 8, m: 1020 })
+        ));
+        assert!(matches!(
+            Matrix::try_random_matrix(&mut *rand, 0, 1024),
+            Err(Error::EmptyMatrix)
+        ));
+        assert!(matches!(
+            Matrix::try_random_matrix(&[0u8; 100][..], 1, 16),
+            Err(Error::ShortRandomSource(_))
+        ));
+        assert!(matches!(
+            Matrix::try_random_from_seed(&[], 8, 1 << 16),
+            Err(Error::InvalidDimensions { .. })
+        ));
+
+        let a = Matrix::try_random_matrix(rand, 2, 64).unwrap();
+        let at = a.try_lookup_table().unwrap();
+        let mut dst = [0u8; 16];
+        assert!(matches!(
+            a.try_compress(&mut dst, &[0u8; 7]),
+            Err(Error::InputLength {
+                got: 7,
+                expected: 8
+            })
+        ));
+        assert!(matches!(
+            at.try_compress(&mut dst[..15], &[0u8; 8]),
+            Err(Error::OutputLength {
+                got: 15,
+                expected: 16
+            })
+        ));
+        assert!(at.try_compress(&mut dst, &[0u8; 8]).is_ok());
+
+        assert!(matches!(
+            try_add(&[1, 2], &[3]),
+            Err(Error::VectorLength {
+                got: 1,
+                expected: 2
+            })
+        ));
+        assert!(matches!(
+            try_sub(&[1], &[2, 3]),
+            Err(Error::VectorLength {
+                got: 2,
+                expected: 1
+            })
+        ));
+        assert_eq!(try_sub(&[1], &[2]).unwrap(), [u64::MAX]);
+    }
+
+    // bits returns the bits of msg, from the least significant bit of each byte.
+    fn bits(msg: &[u8]) -> Vec<bool> {
+        msg.iter()
+            .flat_map(|b| (0..8).map(move |i| (b >> i) & 1 == 1))
+            .collect()
+    }
+
+    #[test]
+    fn linearity() {
+        let a = Matrix::random_from_seed("Algorand".as_bytes(), 8, 1024);
+        let random_bits = || -> Vec<bool> { (0..a.m()).map(|_| rand::random()).collect() };
+        let or = |x: &[bool], y: &[bool]| -> Vec<bool> {
+            x.iter().zip(y).map(|(x, y)| *x || *y).collect()
+        };
+        let and = |x: &[bool], y: &[bool]| -> Vec<bool> {
+            x.iter().zip(y).map(|(x, y)| *x && *y).collect()
+        };
+
+        (0..100).for_each(|_| {
+            let (x, y) = (random_bits(), random_bits());
+            // y without the bits of x is disjoint from x.
+            let y_minus_x: Vec<bool> = x.iter().zip(&y).map(|(x, y)| !*x && *y).collect();
+            assert_eq!(
+                add(&a.mul_bits(&x), &a.mul_bits(&y_minus_x)),
+                a.mul_bits(&or(&x, &y))
+            );
+            assert_eq!(
+                sub(
+                    &add(&a.mul_bits(&x), &a.mul_bits(&y)),
+                    &a.mul_bits(&and(&x, &y))
+                ),
+                a.mul_bits(&or(&x, &y))
+            );
+
+            // Over Z_q, the product is linear for any vectors.
+            let sum: Vec<u64> = x
+                .iter()
+                .zip(&y)
+                .map(|(x, y)| u64::from(*x) + u64::from(*y))
+                .collect();
+            assert_eq!(a.mul_vec(&sum), add(&a.mul_bits(&x), &a.mul_bits(&y)));
+            let v: Vec<u64> = (0..a.m()).map(|_| rand::random()).collect();
+            let w: Vec<u64> = (0..a.m()).map(|_| rand::random()).collect();
+            assert_eq!(a.mul_vec(&add(&v, &w)), add(&a.mul_vec(&v), &a.mul_vec(&w)));
         });
+
+        // Compressing a message is the product with its bits.
+        let msg: Vec<u8> = (0..a.input_len()).map(|_| rand::random::<u8>()).collect();
+        let mut dst = vec![0u8; a.output_len()];
+        a.compress(&mut dst, &msg);
+        let product: Vec<u8> = a
+            .mul_bits(&bits(&msg))
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        assert_eq!(dst, product);
+    }
+
+    #[test]
+    fn access() {
+        let a = Matrix::random_from_seed("Algorand".as_bytes(), 8, 1024);
+        assert_eq!((a.n(), a.m()), (8, 1024));
+        assert_eq!(a.row(3)[700], a.element(3, 700));
+        assert_eq!(a.column(700)[3], a.element(3, 700));
+        let mut x = vec![false; 1024];
+        x[700] = true;
+        assert_eq!(a.mul_bits(&x), a.column(700));
+
+        assert!(matches!(
+            a.try_mul_bits(&x[1..]),
+            Err(Error::VectorLength {
+                got: 1023,
+                expected: 1024
+            })
+        ));
+        assert!(matches!(
+            a.try_mul_vec(&[0; 1025]),
+            Err(Error::VectorLength { .. })
+        ));
+    }
+
+    #[test]
+    fn compression_batch() {
+        const N: usize = 14;
+        const M: usize = N * 64 * 2;
+
+        let rand = &mut Shake256::default().finalize_xof();
+        let a = Matrix::random_matrix(rand, N, M);
+        let at = a.lookup_table();
+
+        // 11 messages exercise both the interleaved messages and the remainder.
+        let src: Vec<u8> = (0..11 * a.input_len())
+            .map(|_| rand::random::<u8>())
+            .collect();
+        let mut want = vec![0u8; 11 * a.output_len()];
+        want.chunks_exact_mut(a.output_len())
+            .zip(src.chunks_exact(a.input_len()))
+            .for_each(|(dst, msg)| a.compress(dst, msg));
+
+        let mut got = vec![0u8; want.len()];
+        a.compress_batch(&mut got, &src);
+        assert_eq!(got, want, "matrix batch and single outputs are different");
+        at.compress_batch(&mut got, &src);
+        assert_eq!(
+            got, want,
+            "lookup table batch and matrix outputs are different"
+        );
+
+        assert!(matches!(
+            at.try_compress_batch(&mut got, &src[1..]),
+            Err(Error::BatchLength { .. })
+        ));
+        assert!(matches!(
+            at.try_compress_batch(&mut got[a.output_len()..], &src),
+            Err(Error::BatchLength { .. })
+        ));
+        assert!(at.try_compress_batch(&mut [], &[]).is_ok());
+    }
+
+    // Xor is a compressor implementing only the required methods, as compressors written before
+    // try_compress did.
+    #[derive(Clone)]
+    struct Xor;
+
+    impl Compressor for Xor {
+        fn compress(&self, dst: &mut [u8], src: &[u8]) {
+            dst[0] = src[0] ^ src[1];
+        }
+
+        fn input_len(&self) -> usize {
+            2
+        }
+
+        fn output_len(&self) -> usize {
+            1
+        }
+    }
+
+    #[test]
+    fn default_try_compress() {
+        let mut dst = [0u8; 2];
+        assert!(matches!(
+            Xor.try_compress(&mut dst[..1], &[1]),
+            Err(Error::InputLength {
+                got: 1,
+                expected: 2
+            })
+        ));
+        assert!(matches!(
+            Xor.try_compress(&mut dst, &[1, 2]),
+            Err(Error::OutputLength {
+                got: 2,
+                expected: 1
+            })
+        ));
+        Xor.try_compress(&mut dst[..1], &[1, 2]).unwrap();
+        assert_eq!(dst[0], 3);
+        Xor.try_compress_batch(&mut dst, &[1, 2, 4, 8]).unwrap();
+        assert_eq!(dst, [3, 12]);
     }
 }
@@ -0,0 +1,211 @@
+//! A compressor whose memory accesses and branches don't depend on the compressed message.
+//!
+//! LookupTable and NibbleLookupTable index their tables with the bytes of the message, so the cache lines they load
+//! leak the message to an attacker sharing the CPU. Matrix reads every element, but relies on the compiler keeping
+//! sum_bits branch-free. When the message depends on a secret, e.g. a salt derived from a key, ConstantTimeMatrix
+//! should be used instead.
+use std::hint;
+
+use super::{check_lengths, compress_or_panic, Compressor, Matrix, SeedFingerprint};
+use crate::Error;
+
+/// ConstantTimeMatrix compresses messages with the elements of a matrix, reading all of them in the same order
+/// whatever the message, and selecting the ones to sum with masks computed from its bits instead of branches.
+/// It's about as fast as compressing with the Matrix itself, and about 10 times slower than with a LookupTable.
+///
+/// The masks go through `std::hint::black_box` so the compiler can't turn them back into branches. This is a
+/// best effort, and the generated code should be checked on the targeted platforms; the `dudect` test of this
+/// module measures whether execution times depend on the message.
+///
+/// It's used with a sumhash core like any other compressor:
+/// ```
+/// use sumhash::compress::{ConstantTimeMatrix, Matrix};
+/// use sumhash::sumhash512core::Sumhash512Core;
+/// use digest::{core_api::CoreWrapper, FixedOutput, Update};
+///
+/// let a = Matrix::random_from_seed("Algorand".as_bytes(), 8, 1024);
+/// let core = Sumhash512Core::with_salt(ConstantTimeMatrix::new(a), [0x42; 64]).unwrap();
+/// let mut h = CoreWrapper::from_core(core);
+/// h.update("hello world".as_bytes());
+/// assert_eq!(h.finalize_fixed(), sumhash::sumhash512_salted([0x42; 64], "hello world").into());
+/// ```
+#[derive(Clone)]
+pub struct ConstantTimeMatrix(Matrix);
+
+impl ConstantTimeMatrix {
+    /// new returns a constant-time compressor with the elements of a.
+    pub fn new(a: Matrix) -> Self {
+        ConstantTimeMatrix(a)
+    }
+
+    /// matrix returns the matrix of the compressor.
+    pub fn matrix(&self) -> &Matrix {
+        &self.0
+    }
+}
+
+impl From<Matrix> for ConstantTimeMatrix {
+    fn from(a: Matrix) -> Self {
+        ConstantTimeMatrix::new(a)
+    }
+}
+
+impl Compressor for ConstantTimeMatrix {
+    fn compress(&self, dst: &mut [u8], msg: &[u8]) {
+        compress_or_panic(self, dst, msg)
+    }
+
+    fn input_len(&self) -> usize {
+        self.0.input_len()
+    }
+
+    fn output_len(&self) -> usize {
+        self.0.output_len()
+    }
+
+    fn seed_fingerprint(&self) -> Option<&SeedFingerprint> {
+        self.0.seed_fingerprint()
+    }
+
+    fn try_compress(&self, dst: &mut [u8], msg: &[u8]) -> Result<(), Error> {
+        check_lengths(self, dst, msg)?;
+
+        dst.fill(0);
+        msg.chunks(MASKED_BYTES).enumerate().for_each(|(c, bytes)| {
+            // The masks of the message bits are hidden from the optimizer, which otherwise turns some of them
+            // back into branches since they're either zero or all ones.
+            let mut masks = [0u64; 8 * MASKED_BYTES];
+            bytes.iter().enumerate().for_each(|(j, &b)| {
+                (0..8).for_each(|k| masks[8 * j + k] = u64::from((b >> k) & 1).wrapping_neg())
+            });
+            hint::black_box(&mut masks);
+
+            let columns = 8 * c * MASKED_BYTES..8 * (c * MASKED_BYTES + bytes.len());
+            dst.chunks_exact_mut(8).enumerate().for_each(|(i, dst)| {
+                let x = self.0.row(i)[columns.clone()]
+                    .iter()
+                    .zip(&masks)
+                    .fold(0u64, |x, (&a, &mask)| x.wrapping_add(a & mask));
+                let sum = u64::from_le_bytes((&*dst).try_into().unwrap()).wrapping_add(x);
+                dst.copy_from_slice(&sum.to_le_bytes());
+            });
+        });
+        Ok(())
+    }
+}
+
+// MASKED_BYTES is the number of message bytes whose masks are computed at once, on the stack.
+const MASKED_BYTES: usize = 16;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn compression() {
+        let a = Matrix::random_from_seed("Algorand".as_bytes(), 8, 1024);
+        let ct = ConstantTimeMatrix::from(a.clone());
+        assert_eq!(ct.input_len(), a.input_len());
+        assert_eq!(ct.output_len(), a.output_len());
+
+        let mut want = vec![0u8; a.output_len()];
+        let mut got = vec![0u8; a.output_len()];
+        (0..100).for_each(|_| {
+            let msg: Vec<u8> = (0..a.input_len()).map(|_| rand::random::<u8>()).collect();
+            a.compress(&mut want, &msg);
+            ct.compress(&mut got, &msg);
+            assert_eq!(got, want, "matrix and constant-time outputs are different");
+        });
+
+        assert!(matches!(
+            ct.try_compress(&mut got, &[0u8; 127]),
+            Err(Error::InputLength { .. })
+        ));
+    }
+
+    // Welch accumulates the execution times of a class of inputs with Welford's algorithm.
+    #[derive(Default)]
+    struct Welch {
+        n: f64,
+        mean: f64,
+        m2: f64,
+    }
+
+    impl Welch {
+        fn push(&mut self, x: f64) {
+            self.n += 1.0;
+            let delta = x - self.mean;
+            self.mean += delta / self.n;
+            self.m2 += delta * (x - self.mean);
+        }
+
+        // t returns Welch's t statistic of the difference between the means of the two classes.
+        fn t(&self, other: &Welch) -> f64 {
+            let var = |w: &Welch| w.m2 / (w.n - 1.0);
+            (self.mean - other.mean) / (var(self) / self.n + var(other) / other.n).sqrt()
+        }
+    }
+
+    // dudect measures the execution times of f on the fixed input and on random inputs of the same length, in a
+    // random order, and returns Welch's t statistic of the two classes. Following dudect, a |t| above 4.5 is
+    // evidence that the execution time depends on the input.
+    fn dudect(samples: usize, fixed: &[u8], mut f: impl FnMut(&[u8])) -> f64 {
+        let inputs: Vec<(bool, Vec<u8>)> = (0..samples)
+            .map(|_| match rand::random::<bool>() {
+                true => (true, fixed.to_vec()),
+                false => (false, (0..fixed.len()).map(|_| rand::random()).collect()),
+            })
+            .collect();
+
+        let times: Vec<f64> = inputs
+            .iter()
+            .map(|(_, input)| {
+                let start = Instant::now();
+                f(hint::black_box(input));
+                start.elapsed().as_nanos() as f64
+            })
+            .collect();
+
+        // The slowest measurements are mostly interrupted ones, and are dropped.
+        let mut sorted = times.clone();
+        sorted.sort_by(|x, y| x.partial_cmp(y).unwrap());
+        let threshold = sorted[samples * 9 / 10];
+
+        let (mut fixed, mut random) = (Welch::default(), Welch::default());
+        inputs
+            .iter()
+            .zip(&times)
+            .filter(|(_, &time)| time <= threshold)
+            .for_each(|((is_fixed, _), &time)| match is_fixed {
+                true => fixed.push(time),
+                false => random.push(time),
+            });
+        fixed.t(&random)
+    }
+
+    #[test]
+    fn dudect_detects_leaks() {
+        // The time to find the first non-zero byte leaks how many zero bytes the input starts with.
+        let t = dudect(20_000, &[0; 1024], |input| {
+            hint::black_box(input.iter().position(|&b| b != 0));
+        });
+        assert!(t.abs() > 4.5, "leak not detected, t = {}", t);
+    }
+
+    // Timing measurements are noisy on shared machines and slow in debug builds, so the test is run in release
+    // mode by the dudect job of the CI workflow, or on demand with `cargo test --release -- --ignored dudect`.
+    #[test]
+    #[ignore]
+    fn dudect_constant_time() {
+        let ct = ConstantTimeMatrix::new(Matrix::random_from_seed("Algorand".as_bytes(), 8, 1024));
+        let mut dst = vec![0u8; ct.output_len()];
+        // An all-zero message selects no element at all, the most different from random messages.
+        let t = dudect(200_000, &[0; 128], |msg| ct.compress(&mut dst, msg));
+        assert!(
+            t.abs() < 4.5,
+            "execution time depends on the message, t = {}",
+            t
+        );
+    }
+}
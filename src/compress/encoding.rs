@@ -0,0 +1,448 @@
+//! Binary encoding of matrices and lookup tables.
+//!
+//! Both types share the same layout, all integers being little-endian:
+//!
+//! | field            | size                                                         |
+//! |------------------|--------------------------------------------------------------|
+//! | magic            | 4 bytes, `SHMX` for a matrix and `SHLT` for a lookup table   |
+//! | version          | u16, currently 1                                             |
+//! | n                | u32, number of rows                                          |
+//! | m                | u32, number of matrix columns (input bits)                   |
+//! | fingerprint flag | u8, 1 if a seed fingerprint is present, 0 otherwise          |
+//! | seed fingerprint | 32 bytes, zeroed when absent                                 |
+//! | padding          | 1 zero byte                                                  |
+//! | words            | u64 each, `[n][m]` for a matrix or `[m/8][256][n]` for a table |
+//! | checksum         | 32 bytes, Shake256 of every preceding byte                   |
+//!
+//! The header is padded to 48 bytes so the words are 8-byte aligned whenever the encoding is, e.g. in a
+//! memory-mapped file, and they're stored in the in-memory order of each type. An encoded lookup table can
+//! thus be used in place with LookupTable::from_bytes.
+use sha3::{
+    digest::{ExtendableOutput, Update},
+    Shake256,
+};
+use std::borrow::Cow;
+use std::io::{self, Read, Write};
+
+use super::{Compressor, LookupTable, Matrix, SeedFingerprint};
+use crate::Error;
+
+const MATRIX_MAGIC: &[u8; 4] = b"SHMX";
+const LOOKUP_TABLE_MAGIC: &[u8; 4] = b"SHLT";
+const VERSION: u16 = 1;
+// HEADER_SIZE is the size of the header, a multiple of 8 so the words that follow it are aligned.
+const HEADER_SIZE: usize = 48;
+pub(crate) const CHECKSUM_SIZE: usize = 32;
+
+// Upper bound of words preallocated while decoding, so a corrupted header can't exhaust memory
+// before the checksum is verified.
+const MAX_PREALLOCATED_WORDS: usize = 1 << 20;
+
+struct Header {
+    n: usize,
+    m: usize,
+    seed_fingerprint: Option<SeedFingerprint>,
+}
+
+// ChecksumWriter hashes everything written through it.
+pub(crate) struct ChecksumWriter<W> {
+    inner: W,
+    xof: Shake256,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        Self {
+            inner,
+            xof: Shake256::default(),
+        }
+    }
+
+    pub(crate) fn write(&mut self, buf: &[u8]) -> Result<(), Error> {
+        self.xof.update(buf);
+        self.inner.write_all(buf).map_err(Error::Io)
+    }
+
+    fn write_words(&mut self, words: &[u64]) -> Result<(), Error> {
+        let buf: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+        self.write(&buf)
+    }
+
+    pub(crate) fn finish(mut self) -> Result<(), Error> {
+        let mut checksum = [0; CHECKSUM_SIZE];
+        self.xof.finalize_xof_into(&mut checksum);
+        self.inner.write_all(&checksum).map_err(Error::Io)?;
+        self.inner.flush().map_err(Error::Io)
+    }
+}
+
+// ChecksumReader hashes everything read through it.
+pub(crate) struct ChecksumReader<R> {
+    inner: R,
+    xof: Shake256,
+}
+
+impl<R: Read> ChecksumReader<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self {
+            inner,
+            xof: Shake256::default(),
+        }
+    }
+
+    pub(crate) fn read(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        read_exact(&mut self.inner, buf)?;
+        self.xof.update(buf);
+        Ok(())
+    }
+
+    fn read_words(&mut self, words: &mut Vec<u64>, count: usize) -> Result<(), Error> {
+        let mut buf = [0; 8 * 256];
+        let mut left = count;
+        while left > 0 {
+            let chunk = left.min(256);
+            self.read(&mut buf[..8 * chunk])?;
+            words.extend(
+                buf[..8 * chunk]
+                    .chunks_exact(8)
+                    .map(|b| u64::from_le_bytes(b.try_into().unwrap())),
+            );
+            left -= chunk;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn finish(mut self) -> Result<(), Error> {
+        let mut want = [0; CHECKSUM_SIZE];
+        self.xof.finalize_xof_into(&mut want);
+        let mut got = [0; CHECKSUM_SIZE];
+        read_exact(&mut self.inner, &mut got)?;
+        if got != want {
+            return Err(Error::ChecksumMismatch);
+        }
+        Ok(())
+    }
+}
+
+fn read_exact<R: Read>(r: &mut R, buf: &mut [u8]) -> Result<(), Error> {
+    r.read_exact(buf).map_err(|err| match err.kind() {
+        io::ErrorKind::UnexpectedEof => Error::InvalidEncoding("truncated data"),
+        _ => Error::Io(err),
+    })
+}
+
+fn write_header<W: Write>(
+    w: &mut ChecksumWriter<W>,
+    magic: &[u8; 4],
+    header: &Header,
+) -> Result<(), Error> {
+    let (n, m) = match (u32::try_from(header.n), u32::try_from(header.m)) {
+        (Ok(n), Ok(m)) => (n, m),
+        _ => {
+            return Err(Error::InvalidDimensions {
+                n: header.n,
+                m: header.m,
+            })
+        }
+    };
+
+    w.write(magic)?;
+    w.write(&VERSION.to_le_bytes())?;
+    w.write(&n.to_le_bytes())?;
+    w.write(&m.to_le_bytes())?;
+    w.write(&[header.seed_fingerprint.is_some() as u8])?;
+    w.write(&header.seed_fingerprint.unwrap_or_default())?;
+    w.write(&[0])
+}
+
+fn read_header<R: Read>(r: &mut ChecksumReader<R>, magic: &[u8; 4]) -> Result<Header, Error> {
+    let mut got = [0; 4];
+    r.read(&mut got)?;
+    if &got != magic {
+        return Err(Error::InvalidEncoding("unexpected magic bytes"));
+    }
+
+    let mut version = [0; 2];
+    r.read(&mut version)?;
+    let version = u16::from_le_bytes(version);
+    if version != VERSION {
+        return Err(Error::UnsupportedVersion(version));
+    }
+
+    let mut dim = [0; 4];
+    r.read(&mut dim)?;
+    let n = u32::from_le_bytes(dim) as usize;
+    r.read(&mut dim)?;
+    let m = u32::from_le_bytes(dim) as usize;
+    if n == 0 || m == 0 {
+        return Err(Error::EmptyMatrix);
+    }
+    if !m.is_multiple_of(8) {
+        return Err(Error::InvalidDimensions { n, m });
+    }
+
+    let mut flag = [0; 1];
+    r.read(&mut flag)?;
+    let mut fingerprint = SeedFingerprint::default();
+    r.read(&mut fingerprint)?;
+    let seed_fingerprint = match flag[0] {
+        0 => None,
+        1 => Some(fingerprint),
+        _ => return Err(Error::InvalidEncoding("invalid seed fingerprint flag")),
+    };
+
+    let mut padding = [0; 1];
+    r.read(&mut padding)?;
+    if padding != [0] {
+        return Err(Error::InvalidEncoding("invalid header padding"));
+    }
+
+    Ok(Header {
+        n,
+        m,
+        seed_fingerprint,
+    })
+}
+
+impl Matrix {
+    /// write_to encodes the matrix into w. See the [`encoding`](crate::compress::encoding) module for the format.
+    pub fn write_to<W: Write>(&self, w: W) -> Result<(), Error> {
+        let mut w = ChecksumWriter::new(w);
+        let header = Header {
+            n: self.n,
+            m: self.m(),
+            seed_fingerprint: self.seed_fingerprint,
+        };
+        write_header(&mut w, MATRIX_MAGIC, &header)?;
+        w.write_words(&self.matrix)?;
+        w.finish()
+    }
+
+    /// read_from decodes a matrix written with write_to from r.
+    pub fn read_from<R: Read>(r: R) -> Result<Self, Error> {
+        let mut r = ChecksumReader::new(r);
+        let header = read_header(&mut r, MATRIX_MAGIC)?;
+
+        let count = header.n.saturating_mul(header.m);
+        let mut matrix = Vec::with_capacity(count.min(MAX_PREALLOCATED_WORDS));
+        r.read_words(&mut matrix, count)?;
+        r.finish()?;
+
+        Ok(Matrix {
+            matrix,
+            n: header.n,
+            seed_fingerprint: header.seed_fingerprint,
+        })
+    }
+}
+
+impl<'a> LookupTable<'a> {
+    /// write_to encodes the lookup table into w. See the [`encoding`](crate::compress::encoding) module for the format.
+    pub fn write_to<W: Write>(&self, w: W) -> Result<(), Error> {
+        let mut w = ChecksumWriter::new(w);
+        let header = Header {
+            n: self.n,
+            m: self.input_len() * 8,
+            seed_fingerprint: self.seed_fingerprint,
+        };
+        write_header(&mut w, LOOKUP_TABLE_MAGIC, &header)?;
+        self.lookup_table
+            .chunks(1 << 16)
+            .try_for_each(|words| w.write_words(words))?;
+        w.finish()
+    }
+
+    /// read_from decodes a lookup table written with write_to from r.
+    pub fn read_from<R: Read>(r: R) -> Result<Self, Error> {
+        let mut r = ChecksumReader::new(r);
+        let header = read_header(&mut r, LOOKUP_TABLE_MAGIC)?;
+
+        // Check the whole table is present before allocating it.
+        let count = table_words(&header);
+        let mut lookup_table = Vec::with_capacity(count.min(MAX_PREALLOCATED_WORDS));
+        r.read_words(&mut lookup_table, count)?;
+        r.finish()?;
+
+        Ok(LookupTable {
+            lookup_table: Cow::Owned(lookup_table),
+            n: header.n,
+            seed_fingerprint: header.seed_fingerprint,
+        })
+    }
+
+    /// from_bytes decodes a lookup table written with write_to, e.g. a memory-mapped file, after
+    /// verifying its checksum.
+    /// The table borrows its sums from bytes when they're 8-byte aligned on a little-endian target, and copies
+    /// them otherwise.
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, Error> {
+        if bytes.len() < HEADER_SIZE + CHECKSUM_SIZE {
+            return Err(Error::InvalidEncoding("truncated data"));
+        }
+        let (data, checksum) = bytes.split_at(bytes.len() - CHECKSUM_SIZE);
+        let mut header_reader = ChecksumReader::new(data);
+        let header = read_header(&mut header_reader, LOOKUP_TABLE_MAGIC)?;
+        let count = table_words(&header);
+        if data.len() != HEADER_SIZE.saturating_add(count.saturating_mul(8)) {
+            return Err(Error::InvalidEncoding("unexpected length"));
+        }
+        let mut xof = Shake256::default();
+        xof.update(data);
+        let mut want = [0; CHECKSUM_SIZE];
+        xof.finalize_xof_into(&mut want);
+        if checksum != want {
+            return Err(Error::ChecksumMismatch);
+        }
+
+        let words = &data[HEADER_SIZE..];
+        // SAFETY: every bit pattern is a valid u64, and align_to only returns the words in the middle
+        // slice if they're aligned.
+        let (prefix, aligned, _) = unsafe { words.align_to::<u64>() };
+        if cfg!(target_endian = "little") && prefix.is_empty() {
+            return Ok(LookupTable::borrowed(
+                aligned,
+                header.n,
+                header.seed_fingerprint,
+            ));
+        }
+        Ok(LookupTable {
+            lookup_table: Cow::Owned(
+                words
+                    .chunks_exact(8)
+                    .map(|w| u64::from_le_bytes(w.try_into().unwrap()))
+                    .collect(),
+            ),
+            n: header.n,
+            seed_fingerprint: header.seed_fingerprint,
+        })
+    }
+}
+
+// table_words returns the number of words of the lookup table described by header.
+fn table_words(header: &Header) -> usize {
+    (header.m / 8).saturating_mul(256).saturating_mul(header.n)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let a = Matrix::random_from_seed("Algorand".as_bytes(), 8, 1024);
+        let at = a.lookup_table();
+
+        let mut buf = Vec::new();
+        a.write_to(&mut buf).unwrap();
+        assert_eq!(
+            buf.len(),
+            4 + 2 + 4 + 4 + 1 + 32 + 1 + 8 * 8 * 1024 + CHECKSUM_SIZE
+        );
+        assert_eq!(HEADER_SIZE, 4 + 2 + 4 + 4 + 1 + 32 + 1);
+        let b = Matrix::read_from(&buf[..]).unwrap();
+        assert_eq!(b.seed_fingerprint(), a.seed_fingerprint());
+        assert!(b.seed_fingerprint().is_some());
+
+        let mut buf = Vec::new();
+        at.write_to(&mut buf).unwrap();
+        let bt = LookupTable::read_from(&buf[..]).unwrap();
+        assert_eq!(bt.seed_fingerprint(), a.seed_fingerprint());
+
+        let msg: Vec<u8> = (0..a.input_len()).map(|_| rand::random::<u8>()).collect();
+        let mut want = [0u8; 64];
+        let mut got = [0u8; 64];
+        a.compress(&mut want, &msg);
+        b.compress(&mut got, &msg);
+        assert_eq!(got, want, "decoded matrix compresses differently");
+        bt.compress(&mut got, &msg);
+        assert_eq!(got, want, "decoded lookup table compresses differently");
+    }
+
+    #[test]
+    fn from_bytes() {
+        let rand = &mut Shake256::default().finalize_xof();
+        let at = Matrix::random_matrix(rand, 4, 64).lookup_table();
+        let mut buf = Vec::new();
+        at.write_to(&mut buf).unwrap();
+
+        // A word-aligned copy of the encoding is borrowed, like a memory-mapped file would be.
+        let mut words = vec![0u64; buf.len().div_ceil(8)];
+        // SAFETY: the words are reinterpreted as bytes, which have no alignment requirement.
+        let aligned = unsafe {
+            std::slice::from_raw_parts_mut(words.as_mut_ptr().cast::<u8>(), words.len() * 8)
+        };
+        aligned[..buf.len()].copy_from_slice(&buf);
+        let aligned = &aligned[..buf.len()];
+        let bt = LookupTable::from_bytes(aligned).unwrap();
+        assert_eq!(bt.lookup_table, at.lookup_table);
+        if cfg!(target_endian = "little") {
+            assert!(matches!(bt.lookup_table, Cow::Borrowed(_)));
+            assert_eq!(
+                bt.lookup_table.as_ptr() as usize,
+                aligned.as_ptr() as usize + HEADER_SIZE
+            );
+        }
+        let owned = bt.into_owned();
+        assert!(matches!(owned.lookup_table, Cow::Owned(_)));
+        drop(words);
+        assert_eq!(owned.lookup_table, at.lookup_table);
+
+        // A misaligned encoding is copied.
+        let mut shifted = vec![0u8];
+        shifted.extend_from_slice(&buf);
+        let bt = LookupTable::from_bytes(&shifted[1..]).unwrap();
+        assert_eq!(bt.lookup_table, at.lookup_table);
+
+        let mut flipped = buf.clone();
+        flipped[HEADER_SIZE] ^= 1;
+        assert!(matches!(
+            LookupTable::from_bytes(&flipped),
+            Err(Error::ChecksumMismatch)
+        ));
+        assert!(matches!(
+            LookupTable::from_bytes(&buf[..buf.len() - 8]),
+            Err(Error::InvalidEncoding(_))
+        ));
+    }
+
+    #[test]
+    fn corrupted() {
+        let rand = &mut Shake256::default().finalize_xof();
+        let a = Matrix::random_matrix(rand, 2, 64);
+        let mut buf = Vec::new();
+        a.write_to(&mut buf).unwrap();
+        assert!(Matrix::read_from(&buf[..])
+            .unwrap()
+            .seed_fingerprint()
+            .is_none());
+
+        assert!(matches!(
+            LookupTable::read_from(&buf[..]),
+            Err(Error::InvalidEncoding(_))
+        ));
+        assert!(matches!(
+            Matrix::read_from(&buf[..buf.len() - 40]),
+            Err(Error::InvalidEncoding(_))
+        ));
+
+        let mut flipped = buf.clone();
+        flipped[100] ^= 1;
+        assert!(matches!(
+            Matrix::read_from(&flipped[..]),
+            Err(Error::ChecksumMismatch)
+        ));
+
+        let mut padded = buf.clone();
+        padded[HEADER_SIZE - 1] = 1;
+        assert!(matches!(
+            Matrix::read_from(&padded[..]),
+            Err(Error::InvalidEncoding(_))
+        ));
+
+        let mut versioned = buf;
+        versioned[4] = 2;
+        assert!(matches!(
+            Matrix::read_from(&versioned[..]),
+            Err(Error::UnsupportedVersion(2))
+        ));
+    }
+}
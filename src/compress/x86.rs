@@ -0,0 +1,220 @@
+//! AVX2 and AVX-512 implementations of the lookup table sums, selected at runtime.
+//!
+//! The column-major layout of LookupTable stores the sums selected by an input byte for consecutive rows
+//! next to each other, so 8 rows are summed with plain vector loads and 64-bit lane additions.
+use std::arch::x86_64::*;
+
+use super::{LookupTable, Sum8, Sum8x4, BATCH_LANES};
+
+// sum8 returns the widest Sum8 implementation supported by the CPU, if any.
+pub(super) fn sum8() -> Option<Sum8> {
+    if is_x86_feature_detected!("avx512f") {
+        Some(sum8_avx512)
+    } else if is_x86_feature_detected!("avx2") {
+        Some(sum8_avx2)
+    } else {
+        None
+    }
+}
+
+// sum8x4 returns the widest Sum8x4 implementation supported by the CPU, if any.
+pub(super) fn sum8x4() -> Option<Sum8x4> {
+    if is_x86_feature_detected!("avx512f") {
+        Some(sum8x4_avx512)
+    } else if is_x86_feature_detected!("avx2") {
+        Some(sum8x4_avx2)
+    } else {
+        None
+    }
+}
+
+fn sum8_avx2(table: &[u64], n: usize, i: usize, msg: &[u8]) -> [u64; 8] {
+    // SAFETY: only returned by sum8 when the CPU supports AVX2.
+    unsafe { sum8_avx2_impl(table, n, i, msg) }
+}
+
+fn sum8_avx512(table: &[u64], n: usize, i: usize, msg: &[u8]) -> [u64; 8] {
+    // SAFETY: only returned by sum8 when the CPU supports AVX-512F.
+    unsafe { sum8_avx512_impl(table, n, i, msg) }
+}
+
+fn sum8x4_avx2(
+    table: &[u64],
+    n: usize,
+    i: usize,
+    msgs: [&[u8]; BATCH_LANES],
+) -> [[u64; 8]; BATCH_LANES] {
+    // SAFETY: only returned by sum8x4 when the CPU supports AVX2.
+    unsafe { sum8x4_avx2_impl(table, n, i, msgs) }
+}
+
+fn sum8x4_avx512(
+    table: &[u64],
+    n: usize,
+    i: usize,
+    msgs: [&[u8]; BATCH_LANES],
+) -> [[u64; 8]; BATCH_LANES] {
+    // SAFETY: only returned by sum8x4 when the CPU supports AVX-512F.
+    unsafe { sum8x4_avx512_impl(table, n, i, msgs) }
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn sum8_avx2_impl(table: &[u64], n: usize, i: usize, msg: &[u8]) -> [u64; 8] {
+    let mut lo = _mm256_setzero_si256();
+    let mut hi = _mm256_setzero_si256();
+    for (j, &b) in msg.iter().enumerate() {
+        let k = LookupTable::index(n, j, b as usize, i);
+        let sums = table[k..k + 8].as_ptr() as *const __m256i;
+        lo = _mm256_add_epi64(lo, _mm256_loadu_si256(sums));
+        hi = _mm256_add_epi64(hi, _mm256_loadu_si256(sums.add(1)));
+    }
+
+    let mut x = [0u64; 8];
+    _mm256_storeu_si256(x.as_mut_ptr() as *mut __m256i, lo);
+    _mm256_storeu_si256(x.as_mut_ptr().add(4) as *mut __m256i, hi);
+    x
+}
+
+#[target_feature(enable = "avx512f")]
+unsafe fn sum8_avx512_impl(table: &[u64], n: usize, i: usize, msg: &[u8]) -> [u64; 8] {
+    let mut acc = _mm512_setzero_si512();
+    for (j, &b) in msg.iter().enumerate() {
+        let k = LookupTable::index(n, j, b as usize, i);
+        let sums = table[k..k + 8].as_ptr() as *const __m512i;
+        acc = _mm512_add_epi64(acc, _mm512_loadu_si512(sums));
+    }
+
+    let mut x = [0u64; 8];
+    _mm512_storeu_si512(x.as_mut_ptr() as *mut __m512i, acc);
+    x
+}
+
+#[target_feature(enable = "avx2")]
+#[allow(clippy::needless_range_loop)]
+unsafe fn sum8x4_avx2_impl(
+    table: &[u64],
+    n: usize,
+    i: usize,
+    msgs: [&[u8]; BATCH_LANES],
+) -> [[u64; 8]; BATCH_LANES] {
+    let mut lo = [_mm256_setzero_si256(); BATCH_LANES];
+    let mut hi = [_mm256_setzero_si256(); BATCH_LANES];
+    for j in 0..msgs[0].len() {
+        for l in 0..BATCH_LANES {
+            let k = LookupTable::index(n, j, msgs[l][j] as usize, i);
+            let sums = table[k..k + 8].as_ptr() as *const __m256i;
+            lo[l] = _mm256_add_epi64(lo[l], _mm256_loadu_si256(sums));
+            hi[l] = _mm256_add_epi64(hi[l], _mm256_loadu_si256(sums.add(1)));
+        }
+    }
+
+    let mut x = [[0u64; 8]; BATCH_LANES];
+    for l in 0..BATCH_LANES {
+        _mm256_storeu_si256(x[l].as_mut_ptr() as *mut __m256i, lo[l]);
+        _mm256_storeu_si256(x[l].as_mut_ptr().add(4) as *mut __m256i, hi[l]);
+    }
+    x
+}
+
+#[target_feature(enable = "avx512f")]
+#[allow(clippy::needless_range_loop)]
+unsafe fn sum8x4_avx512_impl(
+    table: &[u64],
+    n: usize,
+    i: usize,
+    msgs: [&[u8]; BATCH_LANES],
+) -> [[u64; 8]; BATCH_LANES] {
+    let mut acc = [_mm512_setzero_si512(); BATCH_LANES];
+    for j in 0..msgs[0].len() {
+        for l in 0..BATCH_LANES {
+            let k = LookupTable::index(n, j, msgs[l][j] as usize, i);
+            let sums = table[k..k + 8].as_ptr() as *const __m512i;
+            acc[l] = _mm512_add_epi64(acc[l], _mm512_loadu_si512(sums));
+        }
+    }
+
+    let mut x = [[0u64; 8]; BATCH_LANES];
+    for l in 0..BATCH_LANES {
+        _mm512_storeu_si512(x[l].as_mut_ptr() as *mut __m512i, acc[l]);
+    }
+    x
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::compress::{sum8_scalar, sum8x4_scalar, Compressor, Matrix};
+    use sha3::{digest::ExtendableOutput, Shake256};
+
+    #[test]
+    fn compression() {
+        const N: usize = 14;
+        const M: usize = N * 64 * 2;
+
+        let rand = &mut Shake256::default().finalize_xof();
+        let a = Matrix::random_matrix(rand, N, M);
+        let at = a.lookup_table();
+
+        let mut implementations: Vec<(&str, Sum8)> = vec![("scalar", sum8_scalar)];
+        if is_x86_feature_detected!("avx2") {
+            implementations.push(("avx2", sum8_avx2));
+        }
+        if is_x86_feature_detected!("avx512f") {
+            implementations.push(("avx512", sum8_avx512));
+        }
+
+        let mut dst1 = vec![0u8; a.output_len()];
+        let mut dst2 = vec![0u8; a.output_len()];
+
+        (0..1000).for_each(|_| {
+            let msg: Vec<u8> = (0..a.input_len()).map(|_| rand::random::<u8>()).collect();
+            a.compress(&mut dst1, &msg);
+            implementations.iter().for_each(|(name, sum8)| {
+                at.compress_with(*sum8, &mut dst2, &msg);
+                assert_eq!(
+                    dst1, dst2,
+                    "matrix and {} lookup table outputs are different",
+                    name
+                );
+            });
+        });
+    }
+
+    #[test]
+    fn compression_batch() {
+        const N: usize = 14;
+        const M: usize = N * 64 * 2;
+
+        let rand = &mut Shake256::default().finalize_xof();
+        let a = Matrix::random_matrix(rand, N, M);
+        let at = a.lookup_table();
+
+        let mut implementations: Vec<(&str, Sum8x4)> = vec![("scalar", sum8x4_scalar)];
+        if is_x86_feature_detected!("avx2") {
+            implementations.push(("avx2", sum8x4_avx2));
+        }
+        if is_x86_feature_detected!("avx512f") {
+            implementations.push(("avx512", sum8x4_avx512));
+        }
+
+        let mut want = vec![0u8; BATCH_LANES * a.output_len()];
+        let mut got = vec![0u8; BATCH_LANES * a.output_len()];
+
+        (0..250).for_each(|_| {
+            let src: Vec<u8> = (0..BATCH_LANES * a.input_len())
+                .map(|_| rand::random::<u8>())
+                .collect();
+            want.chunks_exact_mut(a.output_len())
+                .zip(src.chunks_exact(a.input_len()))
+                .for_each(|(dst, msg)| a.compress(dst, msg));
+            implementations.iter().for_each(|(name, sum8x4)| {
+                at.compress_interleaved(*sum8x4, &mut got, &src);
+                assert_eq!(
+                    want, got,
+                    "matrix and {} lookup table outputs are different",
+                    name
+                );
+            });
+        });
+    }
+}
@@ -0,0 +1,133 @@
+use std::{error, fmt, io};
+
+/// Error is returned by the fallible operations of this crate.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// InvalidDimensions is returned when a matrix can't have the requested dimensions,
+    /// e.g. because m isn't a multiple of 8.
+    InvalidDimensions {
+        /// n is the requested number of rows.
+        n: usize,
+        /// m is the requested number of columns.
+        m: usize,
+    },
+    /// EmptyMatrix is returned when a matrix would have no rows or no columns.
+    EmptyMatrix,
+    /// ShortRandomSource is returned when the random source fails before providing enough bytes.
+    ShortRandomSource(io::Error),
+    /// InputLength is returned when an input doesn't have the length expected by a compressor.
+    InputLength {
+        /// got is the provided length in bytes.
+        got: usize,
+        /// expected is the required length in bytes.
+        expected: usize,
+    },
+    /// OutputLength is returned when an output doesn't have the length expected by a compressor.
+    OutputLength {
+        /// got is the provided length in bytes.
+        got: usize,
+        /// expected is the required length in bytes.
+        expected: usize,
+    },
+    /// BatchLength is returned when a batch of messages and its outputs don't hold the same number of
+    /// items of the lengths expected by a compressor.
+    BatchLength {
+        /// src is the provided length of the messages in bytes.
+        src: usize,
+        /// dst is the provided length of the outputs in bytes.
+        dst: usize,
+    },
+    /// Io is returned when reading or writing an encoded matrix or lookup table fails.
+    Io(io::Error),
+    /// InvalidEncoding is returned when encoded data is malformed.
+    InvalidEncoding(&'static str),
+    /// UnsupportedVersion is returned when encoded data has a version this crate can't read.
+    UnsupportedVersion(u16),
+    /// ChecksumMismatch is returned when encoded data doesn't match its checksum.
+    ChecksumMismatch,
+    /// IndexOutOfRange is returned when an element position is outside of a Merkle tree.
+    IndexOutOfRange {
+        /// index is the requested position.
+        index: u64,
+        /// len is the number of positions.
+        len: u64,
+    },
+    /// TreeDepth is returned when a tree is too deep for an operation.
+    TreeDepth {
+        /// depth is the depth of the tree.
+        depth: u8,
+        /// max is the maximum supported depth.
+        max: u8,
+    },
+    /// VectorLength is returned when a vector multiplied by a matrix doesn't have one element per column, or
+    /// when a vector added to or subtracted from another doesn't have its length.
+    VectorLength {
+        /// got is the provided number of elements.
+        got: usize,
+        /// expected is the number of columns of the matrix, or the length of the other vector.
+        expected: usize,
+    },
+    /// InvalidProof is returned when a Merkle proof doesn't prove the given elements.
+    InvalidProof(&'static str),
+    /// CompressorMismatch is returned when a hash state is restored with a compressor whose seed fingerprint
+    /// differs from the one of the compressor that wrote it.
+    CompressorMismatch,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidDimensions { n, m } => {
+                write!(f, "invalid matrix dimensions n={}, m={}", n, m)
+            }
+            Error::EmptyMatrix => write!(f, "matrix has no rows or no columns"),
+            Error::ShortRandomSource(err) => write!(f, "random source is too short: {}", err),
+            Error::InputLength { got, expected } => write!(
+                f,
+                "input size is wrong. size is {}, expected {}",
+                got, expected
+            ),
+            Error::OutputLength { got, expected } => write!(
+                f,
+                "output size is wrong. size is {}, expected {}",
+                got, expected
+            ),
+            Error::BatchLength { src, dst } => write!(
+                f,
+                "batch sizes are wrong. messages size is {}, outputs size is {}",
+                src, dst
+            ),
+            Error::Io(err) => write!(f, "i/o error: {}", err),
+            Error::InvalidEncoding(reason) => write!(f, "invalid encoding: {}", reason),
+            Error::UnsupportedVersion(version) => {
+                write!(f, "unsupported encoding version {}", version)
+            }
+            Error::ChecksumMismatch => write!(f, "checksum mismatch"),
+            Error::IndexOutOfRange { index, len } => {
+                write!(f, "index {} is out of range, length is {}", index, len)
+            }
+            Error::TreeDepth { depth, max } => {
+                write!(f, "tree depth {} is larger than {}", depth, max)
+            }
+            Error::VectorLength { got, expected } => write!(
+                f,
+                "vector length is wrong. length is {}, expected {}",
+                got, expected
+            ),
+            Error::InvalidProof(reason) => write!(f, "invalid proof: {}", reason),
+            Error::CompressorMismatch => {
+                write!(f, "state was written with a different compressor")
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::ShortRandomSource(err) | Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Domain separation of the objects hashed with sumhash512, following go-algorand's `protocol.HashID`.
+//!
+//! Algorand never hashes the encoding of an object alone: it is always prefixed with a HashID naming the
+//! type of the object, so that objects of different types can't have the same hash.
+use std::borrow::Cow;
+use std::fmt;
+
+use digest::{FixedOutput, Update};
+
+use crate::sumhash512core::{Sumhash512, DIGEST_SIZE};
+
+/// HashID is a domain separation prefix, hashed before the encoding of an object.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HashID(pub &'static str);
+
+impl HashID {
+    /// as_bytes returns the bytes of the prefix.
+    pub fn as_bytes(&self) -> &'static [u8] {
+        self.0.as_bytes()
+    }
+}
+
+impl fmt::Debug for HashID {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "HashID({:?})", self.0)
+    }
+}
+
+/// MERKLE_ARRAY_NODE is the prefix of the internal nodes of Merkle trees.
+pub const MERKLE_ARRAY_NODE: HashID = HashID("MA");
+/// MERKLE_VECTOR_COMMITMENT_BOTTOM_LEAF is the prefix of the leaves padding vector commitment trees.
+pub const MERKLE_VECTOR_COMMITMENT_BOTTOM_LEAF: HashID = HashID("BL");
+/// KEYS_IN_MSS is the prefix of the Falcon verifying keys committed to by participation keys.
+pub const KEYS_IN_MSS: HashID = HashID("KP");
+/// STATE_PROOF_PART is the prefix of the participants of a state proof.
+pub const STATE_PROOF_PART: HashID = HashID("spp");
+/// STATE_PROOF_SIG is the prefix of the signature slots of a state proof.
+pub const STATE_PROOF_SIG: HashID = HashID("sps");
+/// STATE_PROOF_COIN is the prefix of the coin choices of a state proof.
+pub const STATE_PROOF_COIN: HashID = HashID("spc");
+/// STATE_PROOF_MESSAGE is the prefix of the messages signed for state proofs.
+pub const STATE_PROOF_MESSAGE: HashID = HashID("spm");
+/// STATE_PROOF_VER_CTX is the prefix of the verification context of a state proof.
+pub const STATE_PROOF_VER_CTX: HashID = HashID("spv");
+
+/// Hashable is an object hashed with a domain separation prefix, like go-algorand's `crypto.Hashable`.
+pub trait Hashable {
+    /// to_be_hashed returns the HashID prefix of the object, and its encoding.
+    fn to_be_hashed(&self) -> (HashID, Cow<'_, [u8]>);
+}
+
+/// hash_obj returns the sumhash512 digest of the encoding of obj, prefixed with its HashID.
+pub fn hash_obj<T: Hashable + ?Sized>(obj: &T) -> [u8; DIGEST_SIZE] {
+    let (prefix, data) = obj.to_be_hashed();
+    hash_prefixed(prefix, &[&data])
+}
+
+/// hash_prefixed returns the sumhash512 digest of the concatenation of prefix and parts, without
+/// concatenating them in memory.
+pub fn hash_prefixed(prefix: HashID, parts: &[&[u8]]) -> [u8; DIGEST_SIZE] {
+    let mut h = Sumhash512::default();
+    h.update(prefix.as_bytes());
+    parts.iter().for_each(|part| h.update(part));
+    h.finalize_fixed().into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sumhash512;
+
+    struct Message<'a>(&'a [u8]);
+
+    impl Hashable for Message<'_> {
+        fn to_be_hashed(&self) -> (HashID, Cow<'_, [u8]>) {
+            (STATE_PROOF_MESSAGE, Cow::Borrowed(self.0))
+        }
+    }
+
+    #[test]
+    fn hash_obj() {
+        let msg = "You must be the change you wish to see in the world.".as_bytes();
+        let want = sumhash512([b"spm", msg].concat());
+        assert_eq!(super::hash_obj(&Message(msg)), want);
+        assert_eq!(
+            hash_prefixed(STATE_PROOF_MESSAGE, &[&msg[..10], &msg[10..]]),
+            want
+        );
+        assert_ne!(hash_prefixed(STATE_PROOF_SIG, &[msg]), want);
+        assert_eq!(hash_prefixed(HashID(""), &[msg]), sumhash512(msg));
+    }
+}
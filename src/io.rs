@@ -0,0 +1,187 @@
+//! Hashing of streams with the Algorand instance of sumhash512.
+use std::io::{self, Read, Write};
+
+use digest::{FixedOutput, Update};
+
+use crate::sumhash512core::{Sumhash512, DIGEST_BLOCK_SIZE, DIGEST_SIZE};
+
+// The size of the buffer of hash_reader, a multiple of the block size so that full reads are absorbed
+// without buffering.
+const BUFFER_SIZE: usize = 128 * DIGEST_BLOCK_SIZE;
+
+/// HashingReader wraps a reader and hashes every byte read through it.
+pub struct HashingReader<R> {
+    inner: R,
+    h: Sumhash512,
+}
+
+impl<R: Read> HashingReader<R> {
+    /// new returns a HashingReader reading from inner, hashing with an unsalted Sumhash512.
+    pub fn new(inner: R) -> Self {
+        Self::with_hasher(inner, Sumhash512::default())
+    }
+
+    /// with_hasher returns a HashingReader reading from inner, hashing with h, e.g. a salted Sumhash512.
+    pub fn with_hasher(inner: R, h: Sumhash512) -> Self {
+        Self { inner, h }
+    }
+
+    /// get_ref returns a reference to the wrapped reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// finalize returns the digest of the bytes read so far.
+    pub fn finalize(self) -> [u8; DIGEST_SIZE] {
+        self.h.finalize_fixed().into()
+    }
+
+    /// into_parts returns the wrapped reader and the hasher.
+    pub fn into_parts(self) -> (R, Sumhash512) {
+        (self.inner, self.h)
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.h.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// HashingWriter wraps a writer and hashes every byte written through it.
+pub struct HashingWriter<W> {
+    inner: W,
+    h: Sumhash512,
+}
+
+impl<W: Write> HashingWriter<W> {
+    /// new returns a HashingWriter writing to inner, hashing with an unsalted Sumhash512.
+    pub fn new(inner: W) -> Self {
+        Self::with_hasher(inner, Sumhash512::default())
+    }
+
+    /// with_hasher returns a HashingWriter writing to inner, hashing with h, e.g. a salted Sumhash512.
+    pub fn with_hasher(inner: W, h: Sumhash512) -> Self {
+        Self { inner, h }
+    }
+
+    /// get_ref returns a reference to the wrapped writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// finalize returns the digest of the bytes written so far.
+    pub fn finalize(self) -> [u8; DIGEST_SIZE] {
+        self.h.finalize_fixed().into()
+    }
+
+    /// into_parts returns the wrapped writer and the hasher.
+    pub fn into_parts(self) -> (W, Sumhash512) {
+        (self.inner, self.h)
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    // Only the bytes accepted by the wrapped writer are hashed.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.h.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// hash_reader returns the Sumhash512 digest of everything read from r until its end.
+pub fn hash_reader(mut r: impl Read) -> io::Result<[u8; DIGEST_SIZE]> {
+    let mut h = Sumhash512::default();
+    let mut buf = vec![0; BUFFER_SIZE];
+    loop {
+        match r.read(&mut buf) {
+            Ok(0) => return Ok(h.finalize_fixed().into()),
+            Ok(n) => h.update(&buf[..n]),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{sumhash512, sumhash512_salted, Sumhash512Salted};
+
+    // ChunkedReader returns at most 7 bytes per read, and is interrupted every other read.
+    struct ChunkedReader<'a> {
+        data: &'a [u8],
+        interrupt: bool,
+    }
+
+    impl Read for ChunkedReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.interrupt = !self.interrupt;
+            if self.interrupt {
+                return Err(io::ErrorKind::Interrupted.into());
+            }
+            let n = buf.len().min(self.data.len()).min(7);
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn streams() {
+        let data: Vec<u8> = (0..20000).map(|_| rand::random::<u8>()).collect();
+        let want = sumhash512(&data);
+
+        assert_eq!(hash_reader(&data[..]).unwrap(), want);
+        let chunked = ChunkedReader {
+            data: &data,
+            interrupt: false,
+        };
+        assert_eq!(hash_reader(chunked).unwrap(), want);
+
+        let mut r = HashingReader::new(&data[..]);
+        let mut out = Vec::new();
+        r.read_to_end(&mut out).unwrap();
+        assert_eq!(out, data);
+        assert_eq!(r.finalize(), want);
+
+        let mut w = HashingWriter::new(Vec::new());
+        data.chunks(1000)
+            .for_each(|chunk| w.write_all(chunk).unwrap());
+        w.flush().unwrap();
+        assert_eq!(w.get_ref(), &data);
+        assert_eq!(w.finalize(), want);
+
+        let salt = [0x42; 64];
+        let mut w = HashingWriter::with_hasher(io::sink(), Sumhash512Salted(salt));
+        io::copy(&mut HashingReader::new(&data[..]), &mut w).unwrap();
+        assert_eq!(w.finalize(), sumhash512_salted(salt, &data));
+    }
+
+    #[test]
+    fn partial_writes() {
+        // A writer accepting at most 5 bytes per write, of which only the accepted ones are hashed.
+        struct Short(Vec<u8>);
+        impl Write for Short {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                let n = buf.len().min(5);
+                self.0.extend_from_slice(&buf[..n]);
+                Ok(n)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut w = HashingWriter::new(Short(Vec::new()));
+        assert_eq!(w.write(b"hello world").unwrap(), 5);
+        assert_eq!(w.finalize(), sumhash512("hello"));
+    }
+}
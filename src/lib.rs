@@ -21,30 +21,59 @@
 //!
 //! Using the Algorand instance configuration:
 //! ```
-//! use sumhash::sumhash512core::AlgorandSumhash512Core;
-//! use digest::{core_api::CoreWrapper, FixedOutput, Update};
-//!
-//! let mut h = CoreWrapper::<AlgorandSumhash512Core>::default();
-//! h.update("hello world".as_bytes());
-//! let output = h.finalize_fixed();
-//! println!("Result: {}", hex::encode(&output));
+//! let output = sumhash::sumhash512("hello world");
+//! println!("Result: {}", hex::encode(output));
 //! ```
 //!
-//! Generic flavor providing your own seed.
+//! Hashing incrementally, with a salt:
 //! ```
-//! use sumhash::sumhash512core::Sumhash512Core;
-//! use digest::{core_api::CoreWrapper, FixedOutput, Update};
+//! use digest::{FixedOutput, Update};
 //!
 //! let mut salt = [0; 64];
 //! salt[0] = 0x13;
 //! salt[1] = 0x37;
-//! let mut h = CoreWrapper::from_core(Sumhash512Core::new_with_salt(salt));
+//! let mut h = sumhash::Sumhash512Salted(salt);
+//! h.update("hello ".as_bytes());
+//! h.update("world".as_bytes());
+//! assert_eq!(h.finalize_fixed(), sumhash::sumhash512_salted(salt, "hello world").into());
+//! ```
+//!
+//! Providing your own compressor, e.g. a matrix generated from a different seed.
+//! ```
+//! use sumhash::{compress::Matrix, sumhash512core::Sumhash512Core};
+//! use digest::{core_api::CoreWrapper, FixedOutput, Update};
+//!
+//! let a = Matrix::random_from_seed("my seed".as_bytes(), 8, 1024);
+//! let mut h = CoreWrapper::from_core(Sumhash512Core::new(a.lookup_table()).unwrap());
 //! h.update("hello world".as_bytes());
 //! let output = h.finalize_fixed();
 //! println!("Result: {}", hex::encode(&output));
 //! ```
 //!
+/// error contains the error type returned by the fallible operations.
+mod error;
+pub use error::Error;
+
 /// compress represents the compression function which is performed on a message.
 pub mod compress;
+/// hashid separates the domains of the objects hashed with sumhash512.
+pub mod hashid;
+/// io hashes streams with sumhash512.
+pub mod io;
+/// mac is a keyed hash built on sumhash512 in the NMAC style.
+pub mod mac;
+/// merkle builds Merkle trees over arrays of objects and proves their elements.
+pub mod merkle;
+/// stateproof computes the commitments of Algorand state proofs to their participants and signatures.
+pub mod stateproof;
 /// sumhash512core is a sumhash core implementation for 512 bit output.
 pub mod sumhash512core;
+/// sumhashcore is a sumhash core implementation generic over the output and input sizes.
+pub mod sumhashcore;
+/// testdata loads the fixtures computed with go-algorand.
+#[cfg(test)]
+mod testdata;
+/// tree is a tree hashing mode of sumhash512 for large inputs.
+pub mod tree;
+
+pub use sumhash512core::{sumhash512, sumhash512_salted, Sumhash512, Sumhash512Salted};
@@ -0,0 +1,258 @@
+//! A keyed hash built on sumhash512, computed in constant time.
+//!
+//! Salted sumhash512 alone isn't a MAC: its last compression is linear in the bits of the last block, so the
+//! tags of three messages differing in two bits of their last block give the tag of the fourth one,
+//! `tag(m ^ a ^ b) = tag(m ^ a) + tag(m ^ b) - tag(m)` word by word modulo 2^64. SumhashMac therefore follows
+//! NMAC and hashes the inner digest again with an outer key. The bits of the inner digest, being sums modulo
+//! 2^64, aren't linear in the message, which defeats that forgery. The construction hasn't been analyzed beyond
+//! this, and SumhashHmac is the standard alternative.
+//!
+//! The keys and the messages are compressed by a ConstantTimeMatrix, so their cache accesses and branches don't
+//! depend on them. This makes SumhashMac about 10 times slower than the lookup table based Sumhash512.
+//! SumhashHmac uses the lookup table and **isn't** constant-time.
+use digest::{
+    block_buffer::Eager,
+    core_api::{Buffer, BufferKindUser, CoreWrapper, FixedOutputCore, UpdateCore},
+    crypto_common::{
+        AlgorithmName, Block, BlockSizeUser, InvalidLength, Key, KeyInit, KeySizeUser,
+    },
+    typenum::U64,
+    FixedOutput, MacMarker, Output, OutputSizeUser, Reset, Update,
+};
+use once_cell::sync::Lazy;
+use std::fmt;
+
+use crate::compress::{ConstantTimeMatrix, Matrix};
+#[cfg(feature = "hmac")]
+use crate::sumhash512core::AlgorandSumhash512Core;
+use crate::sumhash512core::{Sumhash512Core, DIGEST_BLOCK_SIZE};
+
+/// SumhashMac is a keyed hash based on the Algorand instance of sumhash512.
+/// See the [module](crate::mac) documentation and [`SumhashMacCore`] for the construction.
+pub type SumhashMac = CoreWrapper<SumhashMacCore>;
+
+/// SumhashHmac is HMAC instantiated with the Algorand instance of sumhash512, for the environments which
+/// mandate HMAC. Unlike SumhashMac, it isn't constant-time.
+#[cfg(feature = "hmac")]
+pub type SumhashHmac = hmac::Hmac<CoreWrapper<AlgorandSumhash512Core>>;
+
+// The prefixes of the key when deriving the inner and outer salts, separating them from each other and from
+// other uses of sumhash512.
+const INNER_KEY_PREFIX: &[u8] = b"sumhash512 mac inner key";
+const OUTER_KEY_PREFIX: &[u8] = b"sumhash512 mac outer key";
+
+// ConstantTimeCore is the Algorand instance of sumhash512 with the constant-time compressor.
+type ConstantTimeCore = Sumhash512Core<&'static ConstantTimeMatrix>;
+
+// MATRIX is the Algorand matrix, compressing the keys and messages in constant time.
+static MATRIX: Lazy<ConstantTimeMatrix> =
+    Lazy::new(|| ConstantTimeMatrix::new(Matrix::random_from_seed("Algorand".as_bytes(), 8, 1024)));
+
+/// SumhashMacCore is an NMAC core built on the salt of sumhash512.
+///
+/// A key of any length is derived into the 64 byte inner and outer salts
+/// `sumhash512("sumhash512 mac inner key" || key)` and `sumhash512("sumhash512 mac outer key" || key)`.
+/// The tag is `sumhash512_salted(outer, sumhash512_salted(inner, message))`, every hash being computed with
+/// the Algorand matrix as a ConstantTimeMatrix.
+#[derive(Clone)]
+pub struct SumhashMacCore {
+    inner: ConstantTimeCore,
+    outer: ConstantTimeCore,
+}
+
+impl KeySizeUser for SumhashMacCore {
+    /// KeySize is the size of keys given to KeyInit::new. Keys of any length are accepted by new_from_slice.
+    type KeySize = U64;
+}
+
+impl KeyInit for SumhashMacCore {
+    fn new(key: &Key<Self>) -> Self {
+        Self::new_from_slice(key).unwrap()
+    }
+
+    fn new_from_slice(key: &[u8]) -> Result<Self, InvalidLength> {
+        Ok(Self {
+            inner: salted_core(&derive_salt(INNER_KEY_PREFIX, key)),
+            outer: salted_core(&derive_salt(OUTER_KEY_PREFIX, key)),
+        })
+    }
+}
+
+// derive_salt hashes the key with the prefix into a salt.
+fn derive_salt(prefix: &[u8], key: &[u8]) -> [u8; DIGEST_BLOCK_SIZE] {
+    let mut h = CoreWrapper::from_core(ConstantTimeCore::new_unchecked(&MATRIX, None));
+    h.update(prefix);
+    h.update(key);
+    h.finalize_fixed().into()
+}
+
+// salted_core returns a constant-time core with salt.
+fn salted_core(salt: &[u8; DIGEST_BLOCK_SIZE]) -> ConstantTimeCore {
+    ConstantTimeCore::new_unchecked(&MATRIX, Some((*salt).into()))
+}
+
+impl MacMarker for SumhashMacCore {}
+
+impl fmt::Debug for SumhashMacCore {
+    // The cores are left out, since their salts are derived from the key.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SumhashMacCore { .. }")
+    }
+}
+
+impl AlgorithmName for SumhashMacCore {
+    fn write_alg_name(f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SumhashMac512")
+    }
+}
+
+impl BlockSizeUser for SumhashMacCore {
+    type BlockSize = <ConstantTimeCore as BlockSizeUser>::BlockSize;
+}
+
+impl BufferKindUser for SumhashMacCore {
+    type BufferKind = Eager;
+}
+
+impl OutputSizeUser for SumhashMacCore {
+    type OutputSize = <ConstantTimeCore as OutputSizeUser>::OutputSize;
+}
+
+impl UpdateCore for SumhashMacCore {
+    fn update_blocks(&mut self, blocks: &[Block<Self>]) {
+        self.inner.update_blocks(blocks)
+    }
+}
+
+impl FixedOutputCore for SumhashMacCore {
+    fn finalize_fixed_core(&mut self, buffer: &mut Buffer<Self>, out: &mut Output<Self>) {
+        let mut digest = Output::<Self>::default();
+        self.inner.finalize_fixed_core(buffer, &mut digest);
+        let mut outer = CoreWrapper::from_core(self.outer.clone());
+        outer.update(&digest);
+        outer.finalize_into(out);
+    }
+}
+
+impl Reset for SumhashMacCore {
+    fn reset(&mut self) {
+        self.inner.reset()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{sumhash512, sumhash512_salted};
+    use digest::Mac;
+
+    // forge returns the tag of m ^ a ^ b from the tags of m ^ a, m ^ b and m, which is right when the tag is
+    // linear in the bits of the message.
+    fn forge(a: &[u8], b: &[u8], m: &[u8]) -> Vec<u8> {
+        let word = |x: &[u8], i: usize| u64::from_le_bytes(x[8 * i..8 * i + 8].try_into().unwrap());
+        (0..a.len() / 8)
+            .flat_map(|i| {
+                let x = word(a, i).wrapping_add(word(b, i)).wrapping_sub(word(m, i));
+                x.to_le_bytes()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn linear_forgery() {
+        // The message and the two bit flips are in the last block.
+        let m = [0x3cu8; 32];
+        let (mut ma, mut mb, mut mab) = (m, m, m);
+        ma[0] ^= 1;
+        mb[17] ^= 0x20;
+        mab[0] ^= 1;
+        mab[17] ^= 0x20;
+
+        // The forgery works against salted sumhash512, even with a secret salt.
+        let salt = [0x5a; 64];
+        let h = |msg: &[u8]| sumhash512_salted(salt, msg);
+        assert_eq!(forge(&h(&ma), &h(&mb), &h(&m)), h(&mab));
+
+        // It doesn't against the MAC.
+        let tag = |msg: &[u8]| {
+            let mut mac: SumhashMac = Mac::new_from_slice(b"secret key").unwrap();
+            Mac::update(&mut mac, msg);
+            mac.finalize().into_bytes()
+        };
+        let forged = forge(&tag(&ma), &tag(&mb), &tag(&m));
+        let mut mac: SumhashMac = Mac::new_from_slice(b"secret key").unwrap();
+        Mac::update(&mut mac, &mab);
+        assert!(mac.verify_slice(&forged).is_err(), "forged tag accepted");
+    }
+
+    #[test]
+    fn mac() {
+        let msg = "You must be the change you wish to see in the world. -Mahatma Gandhi";
+        for key in [&b""[..], b"key", &[0x5a; 64], &[0xa5; 300]] {
+            let mut m: SumhashMac = Mac::new_from_slice(key).unwrap();
+            Mac::update(&mut m, msg.as_bytes());
+            let tag = m.finalize().into_bytes();
+
+            // The tag is the outer salted digest of the inner salted digest, with the salts derived from the key.
+            let salt = |prefix: &[u8]| sumhash512([prefix, key].concat());
+            let inner = sumhash512_salted(salt(INNER_KEY_PREFIX), msg);
+            let want = sumhash512_salted(salt(OUTER_KEY_PREFIX), inner);
+            assert_eq!(tag[..], want[..], "key of length {}", key.len());
+
+            let m = || {
+                let mut m: SumhashMac = Mac::new_from_slice(key).unwrap();
+                Mac::update(&mut m, msg.as_bytes());
+                m
+            };
+            assert!(m().verify_slice(&tag).is_ok());
+            assert!(m().verify_slice(&tag[..32]).is_err());
+            let mut forged = tag;
+            forged[0] ^= 1;
+            assert!(m().verify_slice(&forged).is_err());
+        }
+
+        let key = [0x5a; 64];
+        let a: SumhashMac = Mac::new(&key.into());
+        let b: SumhashMac = Mac::new_from_slice(&key).unwrap();
+        let c: SumhashMac = Mac::new_from_slice(&key[1..]).unwrap();
+        let a = a.finalize().into_bytes();
+        assert_eq!(a, b.finalize().into_bytes());
+        assert_ne!(a, c.finalize().into_bytes());
+    }
+
+    #[test]
+    fn reset() {
+        let mut m: SumhashMac = Mac::new_from_slice(b"key").unwrap();
+        Mac::update(&mut m, b"first message");
+        let first = m.finalize_reset().into_bytes();
+        Mac::update(&mut m, b"second message");
+        let second = m.finalize_reset().into_bytes();
+
+        let mut n: SumhashMac = Mac::new_from_slice(b"key").unwrap();
+        Mac::update(&mut n, b"second message");
+        assert_eq!(second, n.finalize().into_bytes());
+        assert_ne!(first, second);
+    }
+
+    #[cfg(feature = "hmac")]
+    #[test]
+    fn hmac() {
+        let key = b"key";
+        let msg = b"The quick brown fox jumps over the lazy dog";
+        let mut m: SumhashHmac = Mac::new_from_slice(key).unwrap();
+        Mac::update(&mut m, msg);
+        let tag = m.finalize().into_bytes();
+
+        // H((K ^ opad) || H((K ^ ipad) || m)), with K padded to the block size.
+        let mut k = [0u8; 64];
+        k[..key.len()].copy_from_slice(key);
+        let mut h = CoreWrapper::<AlgorandSumhash512Core>::default();
+        h.update(&k.map(|b| b ^ 0x36));
+        h.update(msg);
+        let inner = h.finalize_fixed();
+        let mut h = CoreWrapper::<AlgorandSumhash512Core>::default();
+        h.update(&k.map(|b| b ^ 0x5c));
+        h.update(&inner);
+        assert_eq!(tag, h.finalize_fixed());
+    }
+}
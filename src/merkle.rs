@@ -0,0 +1,588 @@
+//! Merkle trees over arrays of objects, compatible with go-algorand's `merklearray` package instantiated with
+//! sumhash512, as used by Algorand state proofs.
+//!
+//! Every element of the array is hashed with its HashID prefix into a leaf. Each internal node is the hash of
+//! `"MA" || left || right`, where a missing right child (at the end of an odd sized level) is replaced by a
+//! digest of zeros. The root of a tree with a single element is its leaf, and an empty tree has no root.
+//!
+//! Proofs cover any set of positions: the path holds the siblings which can't be computed from the proven
+//! elements, level by level from the leaves, in increasing position order. A sibling beyond the end of its
+//! level is encoded as `None`.
+//!
+//! Vector commitment trees are built with
+//! [`Tree::build_vector_commitment`](crate::merkle::Tree::build_vector_commitment), following go-algorand's
+//! `BuildVectorCommitmentTree`: the array is padded to a power of two with bottom leaves, hashed as `"BL"`, and
+//! the element at position i is placed at the leaf whose position is i with its bits reversed.
+use std::borrow::Cow;
+
+use crate::hashid::{hash_obj, hash_prefixed, HashID};
+use crate::hashid::{MERKLE_ARRAY_NODE, MERKLE_VECTOR_COMMITMENT_BOTTOM_LEAF};
+use crate::sumhash512core::DIGEST_SIZE;
+use crate::Error;
+
+pub use crate::hashid::Hashable;
+
+/// Digest is the sumhash512 digest of a node of a tree.
+pub type Digest = [u8; DIGEST_SIZE];
+
+/// MAX_ENCODED_TREE_DEPTH is the maximum depth of a tree whose single leaf proofs have a fixed length
+/// representation.
+pub const MAX_ENCODED_TREE_DEPTH: u8 = 16;
+
+/// Tree is a Merkle tree over an array of objects.
+#[derive(Clone, Debug)]
+pub struct Tree {
+    // The leaves first, up to the root.
+    levels: Vec<Vec<Digest>>,
+    len: u64,
+    vector_commitment: bool,
+}
+
+/// Proof is a proof that elements are at given positions of a tree.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Proof {
+    /// path is the siblings needed to recompute the root, `None` for siblings missing from the tree.
+    pub path: Vec<Option<Digest>>,
+    /// tree_depth is the number of levels above the leaves.
+    pub tree_depth: u8,
+}
+
+/// SingleLeafProof is a proof for a single element, whose path holds a sibling for every level.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SingleLeafProof(pub Proof);
+
+impl SingleLeafProof {
+    /// fixed_length_hashable_representation returns the depth of the tree as a byte, followed by
+    /// `MAX_ENCODED_TREE_DEPTH - depth` digests of zeros and the path from the leaf, missing siblings being
+    /// zeros, as go-algorand's `GetFixedLengthHashableRepresentation`.
+    pub fn fixed_length_hashable_representation(&self) -> Vec<u8> {
+        let depth = self.0.tree_depth;
+        let mut out = Vec::with_capacity(1 + MAX_ENCODED_TREE_DEPTH as usize * DIGEST_SIZE);
+        out.push(depth);
+        out.resize(
+            1 + MAX_ENCODED_TREE_DEPTH.saturating_sub(depth) as usize * DIGEST_SIZE,
+            0,
+        );
+        (0..depth as usize).for_each(|i| match self.0.path.get(i) {
+            Some(Some(sibling)) => out.extend_from_slice(sibling),
+            _ => out.extend_from_slice(&[0; DIGEST_SIZE]),
+        });
+        out
+    }
+}
+
+impl Tree {
+    /// build returns the tree over the elements of array.
+    pub fn build<T: Hashable>(array: &[T]) -> Self {
+        Self::from_leaves(array.iter().map(hash_obj).collect(), false)
+    }
+
+    /// build_vector_commitment returns the vector commitment tree over the elements of array.
+    pub fn build_vector_commitment<T: Hashable>(array: &[T]) -> Self {
+        let depth = vector_commitment_depth(array.len() as u64);
+        let padded_len = if array.is_empty() { 0 } else { 1u64 << depth };
+        let leaves = (0..padded_len)
+            .map(|pos| match array.get(reverse_bits(pos, depth) as usize) {
+                Some(elem) => hash_obj(elem),
+                None => hash_obj(&BottomLeaf),
+            })
+            .collect();
+        let mut tree = Self::from_leaves(leaves, true);
+        tree.len = array.len() as u64;
+        tree
+    }
+
+    fn from_leaves(leaves: Vec<Digest>, vector_commitment: bool) -> Self {
+        let len = leaves.len() as u64;
+        let mut levels = vec![leaves];
+        while levels[levels.len() - 1].len() > 1 {
+            let up = levels[levels.len() - 1]
+                .chunks(2)
+                .map(|pair| node_hash(&pair[0], pair.get(1)))
+                .collect();
+            levels.push(up);
+        }
+        Self {
+            levels,
+            len,
+            vector_commitment,
+        }
+    }
+
+    /// root returns the root of the tree, or None if the tree is empty.
+    pub fn root(&self) -> Option<Digest> {
+        self.levels[self.levels.len() - 1].first().copied()
+    }
+
+    /// len returns the number of elements in the tree.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// is_empty returns whether the tree has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// depth returns the number of levels above the leaves.
+    pub fn depth(&self) -> u8 {
+        (self.levels.len() - 1) as u8
+    }
+
+    /// prove returns a proof for the elements at positions idxs, which may be in any order.
+    /// It fails if a position is out of range. Like in go-algorand, the proof of no elements has a depth of 0.
+    pub fn prove(&self, idxs: &[u64]) -> Result<Proof, Error> {
+        if idxs.is_empty() {
+            return Ok(Proof::default());
+        }
+        if let Some(&index) = idxs.iter().find(|&&pos| pos >= self.len()) {
+            return Err(Error::IndexOutOfRange {
+                index,
+                len: self.len(),
+            });
+        }
+        let mut positions: Vec<u64> = match self.vector_commitment {
+            true => idxs
+                .iter()
+                .map(|&pos| reverse_bits(pos, self.depth()))
+                .collect(),
+            false => idxs.to_vec(),
+        };
+        positions.sort_unstable();
+        positions.dedup();
+
+        let mut path = Vec::new();
+        for level in &self.levels[..self.levels.len() - 1] {
+            let mut up = Vec::with_capacity(positions.len());
+            let mut i = 0;
+            while i < positions.len() {
+                let pos = positions[i];
+                if i + 1 < positions.len() && positions[i + 1] == pos ^ 1 {
+                    i += 1;
+                } else {
+                    path.push(level.get((pos ^ 1) as usize).copied());
+                }
+                up.push(pos / 2);
+                i += 1;
+            }
+            positions = up;
+        }
+
+        Ok(Proof {
+            path,
+            tree_depth: self.depth(),
+        })
+    }
+
+    /// prove_single_leaf returns a proof for the element at position idx.
+    /// It fails if the position is out of range, or if the tree is deeper than MAX_ENCODED_TREE_DEPTH.
+    pub fn prove_single_leaf(&self, idx: u64) -> Result<SingleLeafProof, Error> {
+        if self.depth() > MAX_ENCODED_TREE_DEPTH {
+            return Err(Error::TreeDepth {
+                depth: self.depth(),
+                max: MAX_ENCODED_TREE_DEPTH,
+            });
+        }
+        self.prove(&[idx]).map(SingleLeafProof)
+    }
+}
+
+/// verify checks that proof proves the elements, given with their positions, are in the tree with root.
+pub fn verify<T: Hashable>(root: &Digest, elems: &[(u64, T)], proof: &Proof) -> Result<(), Error> {
+    let leaves = elems
+        .iter()
+        .map(|(pos, elem)| (*pos, hash_obj(elem)))
+        .collect();
+    verify_leaves(root, leaves, proof)
+}
+
+/// verify_vector_commitment checks that proof proves the elements, given with their positions, are in the
+/// vector commitment tree with root.
+pub fn verify_vector_commitment<T: Hashable>(
+    root: &Digest,
+    elems: &[(u64, T)],
+    proof: &Proof,
+) -> Result<(), Error> {
+    let depth = proof.tree_depth;
+    let leaves = elems
+        .iter()
+        .map(|(pos, elem)| {
+            if depth < 64 && *pos >> depth != 0 {
+                return Err(Error::IndexOutOfRange {
+                    index: *pos,
+                    len: 1 << depth,
+                });
+            }
+            Ok((reverse_bits(*pos, depth), hash_obj(elem)))
+        })
+        .collect::<Result<_, _>>()?;
+    verify_leaves(root, leaves, proof)
+}
+
+fn verify_leaves(root: &Digest, mut layer: Vec<(u64, Digest)>, proof: &Proof) -> Result<(), Error> {
+    if layer.is_empty() {
+        if !proof.path.is_empty() {
+            return Err(Error::InvalidProof("non-empty proof for no elements"));
+        }
+        return Ok(());
+    }
+
+    layer.sort_unstable_by_key(|(pos, _)| *pos);
+    if layer.windows(2).any(|w| w[0].0 == w[1].0) {
+        return Err(Error::InvalidProof("duplicate positions"));
+    }
+    if proof.tree_depth < 64 {
+        let len = 1 << proof.tree_depth;
+        if let Some(&(index, _)) = layer.iter().find(|(pos, _)| *pos >= len) {
+            return Err(Error::IndexOutOfRange { index, len });
+        }
+    }
+
+    let mut path = proof.path.iter();
+    for _ in 0..proof.tree_depth {
+        let mut up = Vec::with_capacity(layer.len());
+        let mut i = 0;
+        while i < layer.len() {
+            let (pos, hash) = layer[i];
+            let sibling = if i + 1 < layer.len() && layer[i + 1].0 == pos ^ 1 {
+                i += 1;
+                Some(layer[i].1)
+            } else {
+                *path
+                    .next()
+                    .ok_or(Error::InvalidProof("path is too short"))?
+            };
+            let node = match (pos & 1, sibling) {
+                (0, sibling) => node_hash(&hash, sibling.as_ref()),
+                (_, Some(sibling)) => node_hash(&sibling, Some(&hash)),
+                (_, None) => return Err(Error::InvalidProof("missing left sibling")),
+            };
+            up.push((pos / 2, node));
+            i += 1;
+        }
+        layer = up;
+    }
+
+    if path.next().is_some() {
+        return Err(Error::InvalidProof("path is too long"));
+    }
+    if layer[0].1 != *root {
+        return Err(Error::InvalidProof("root mismatch"));
+    }
+    Ok(())
+}
+
+// BottomLeaf pads a vector commitment tree to a power of two leaves.
+struct BottomLeaf;
+
+impl Hashable for BottomLeaf {
+    fn to_be_hashed(&self) -> (HashID, Cow<'_, [u8]>) {
+        (MERKLE_VECTOR_COMMITMENT_BOTTOM_LEAF, Cow::Borrowed(&[]))
+    }
+}
+
+// vector_commitment_depth returns the depth of a vector commitment tree of len elements, the number of bits
+// of len - 1.
+fn vector_commitment_depth(len: u64) -> u8 {
+    (u64::BITS - len.saturating_sub(1).leading_zeros()) as u8
+}
+
+// reverse_bits returns pos with its depth lowest bits reversed, which maps a position of a vector commitment
+// to the position of its leaf, and back.
+fn reverse_bits(pos: u64, depth: u8) -> u64 {
+    pos.reverse_bits()
+        .checked_shr(64 - depth as u32)
+        .unwrap_or(0)
+}
+
+// node_hash returns the hash of an internal node, a missing right child being replaced with zeros.
+fn node_hash(left: &Digest, right: Option<&Digest>) -> Digest {
+    hash_prefixed(
+        MERKLE_ARRAY_NODE,
+        &[left, right.unwrap_or(&[0; DIGEST_SIZE])],
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sumhash512;
+
+    struct TestElement(u64);
+
+    impl Hashable for TestElement {
+        fn to_be_hashed(&self) -> (HashID, Cow<'_, [u8]>) {
+            (HashID("TE"), Cow::Owned(self.0.to_le_bytes().to_vec()))
+        }
+    }
+
+    fn leaf(i: u64) -> Digest {
+        let mut data = b"TE".to_vec();
+        data.extend_from_slice(&i.to_le_bytes());
+        sumhash512(data)
+    }
+
+    fn node(l: &Digest, r: &Digest) -> Digest {
+        let mut data = b"MA".to_vec();
+        data.extend_from_slice(l);
+        data.extend_from_slice(r);
+        sumhash512(data)
+    }
+
+    // The roots and paths are recomputed from the merklearray construction with the one-shot sumhash512, and
+    // compared with go-algorand by the go_algorand tests.
+    #[test]
+    fn root() {
+        let tree = Tree::build::<TestElement>(&[]);
+        assert!(tree.is_empty());
+        assert_eq!(tree.root(), None);
+
+        let tree = Tree::build(&[TestElement(0)]);
+        assert_eq!(tree.depth(), 0);
+        assert_eq!(tree.root(), Some(leaf(0)));
+
+        // The last node of an odd sized level gets a right child of zeros.
+        let elems: Vec<_> = (0..5).map(TestElement).collect();
+        let tree = Tree::build(&elems);
+        let zero = [0; 64];
+        let want = node(
+            &node(&node(&leaf(0), &leaf(1)), &node(&leaf(2), &leaf(3))),
+            &node(&node(&leaf(4), &zero), &zero),
+        );
+        assert_eq!(tree.len(), 5);
+        assert_eq!(tree.depth(), 3);
+        assert_eq!(tree.root(), Some(want));
+    }
+
+    #[test]
+    fn proofs() {
+        for n in 1..20u64 {
+            let elems: Vec<_> = (0..n).map(TestElement).collect();
+            let tree = Tree::build(&elems);
+            let root = tree.root().unwrap();
+
+            let sets: Vec<Vec<u64>> = vec![
+                vec![],
+                vec![0],
+                vec![n - 1],
+                (0..n).collect(),
+                (0..n).rev().filter(|i| i % 3 == 0).collect(),
+            ];
+            for idxs in sets {
+                let proof = tree.prove(&idxs).unwrap();
+                let proven: Vec<_> = idxs.iter().map(|&i| (i, TestElement(i))).collect();
+                verify(&root, &proven, &proof).unwrap();
+
+                if let Some(&i) = idxs.first() {
+                    let wrong: Vec<_> = idxs.iter().map(|&j| (j, TestElement(j + 1))).collect();
+                    assert!(verify(&root, &wrong, &proof).is_err(), "{} elements", n);
+
+                    let mut short = proof.clone();
+                    if short.path.pop().is_some() {
+                        assert!(verify(&root, &proven, &short).is_err());
+                    }
+                    let mut long = proof.clone();
+                    long.path.push(None);
+                    assert!(verify(&root, &proven, &long).is_err());
+                    assert!(verify(&root, &[(i ^ 1, TestElement(i))], &proof).is_err());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn proof_path() {
+        let elems: Vec<_> = (0..5).map(TestElement).collect();
+        let tree = Tree::build(&elems);
+
+        let proof = tree.prove(&[4]).unwrap();
+        let want = vec![
+            None,
+            None,
+            Some(node(&node(&leaf(0), &leaf(1)), &node(&leaf(2), &leaf(3)))),
+        ];
+        assert_eq!(proof.path, want);
+
+        let proof = tree.prove(&[3, 0, 3]).unwrap();
+        let want = vec![
+            Some(leaf(1)),
+            Some(leaf(2)),
+            Some(node(&node(&leaf(4), &[0; 64]), &[0; 64])),
+        ];
+        assert_eq!(proof.path, want);
+
+        assert!(matches!(
+            tree.prove(&[5]),
+            Err(Error::IndexOutOfRange { index: 5, len: 5 })
+        ));
+        assert_eq!(tree.prove(&[]).unwrap(), Proof::default());
+        assert!(verify::<TestElement>(&tree.root().unwrap(), &[], &Proof::default()).is_ok());
+    }
+
+    #[test]
+    #[ignore = "needs the fixtures generated by testdata/go-algorand"]
+    fn go_algorand_merklearray() {
+        use crate::testdata::{digest, fixtures, path, uints};
+
+        for tree in fixtures("tree") {
+            let n: u64 = tree[0].parse().unwrap();
+            let elems: Vec<_> = (0..n).map(TestElement).collect();
+            assert_eq!(
+                Tree::build(&elems).root(),
+                Some(digest(&tree[1])),
+                "root of {} elements",
+                n
+            );
+        }
+
+        // The proofs cover odd sized levels, whose last node has a missing sibling.
+        for proof in fixtures("proof") {
+            let n: u64 = proof[0].parse().unwrap();
+            let idxs = uints(&proof[1]);
+            let want = Proof {
+                path: path(&proof[3]),
+                tree_depth: proof[2].parse().unwrap(),
+            };
+            let elems: Vec<_> = (0..n).map(TestElement).collect();
+            let tree = Tree::build(&elems);
+            assert_eq!(
+                tree.prove(&idxs).unwrap(),
+                want,
+                "{} elements, {:?}",
+                n,
+                idxs
+            );
+
+            let proven: Vec<_> = idxs.iter().map(|&i| (i, TestElement(i))).collect();
+            verify(&tree.root().unwrap(), &proven, &want).unwrap();
+        }
+    }
+
+    #[test]
+    fn vector_commitment() {
+        let tree = Tree::build_vector_commitment::<TestElement>(&[]);
+        assert!(tree.is_empty());
+        assert_eq!(tree.root(), None);
+        let tree = Tree::build_vector_commitment(&[TestElement(0)]);
+        assert_eq!(tree.root(), Some(leaf(0)));
+
+        // The leaf at position p holds the element at position p with its 3 bits reversed, or a bottom leaf.
+        let elems: Vec<_> = (0..5).map(TestElement).collect();
+        let tree = Tree::build_vector_commitment(&elems);
+        let bottom = sumhash512("BL");
+        let want = node(
+            &node(&node(&leaf(0), &leaf(4)), &node(&leaf(2), &bottom)),
+            &node(&node(&leaf(1), &bottom), &node(&leaf(3), &bottom)),
+        );
+        assert_eq!(tree.len(), 5);
+        assert_eq!(tree.depth(), 3);
+        assert_eq!(tree.root(), Some(want));
+
+        let proof = tree.prove(&[1]).unwrap();
+        assert_eq!(
+            proof.path,
+            vec![
+                Some(bottom),
+                Some(node(&leaf(3), &bottom)),
+                Some(node(&node(&leaf(0), &leaf(4)), &node(&leaf(2), &bottom))),
+            ]
+        );
+        assert!(tree.prove(&[5]).is_err());
+        assert_eq!(tree.prove(&[]).unwrap(), Proof::default());
+        verify_vector_commitment::<TestElement>(&tree.root().unwrap(), &[], &Proof::default())
+            .unwrap();
+
+        for n in 1..20u64 {
+            let elems: Vec<_> = (0..n).map(TestElement).collect();
+            let tree = Tree::build_vector_commitment(&elems);
+            let root = tree.root().unwrap();
+            let idxs: Vec<u64> = (0..n).filter(|i| i % 3 != 1).collect();
+            let proof = tree.prove(&idxs).unwrap();
+            let proven: Vec<_> = idxs.iter().map(|&i| (i, TestElement(i))).collect();
+            verify_vector_commitment(&root, &proven, &proof).unwrap();
+            let wrong: Vec<_> = idxs.iter().map(|&i| (i, TestElement(i + 1))).collect();
+            assert!(verify_vector_commitment(&root, &wrong, &proof).is_err());
+        }
+    }
+
+    #[test]
+    #[ignore = "needs the fixtures generated by testdata/go-algorand"]
+    fn go_algorand_vector_commitment() {
+        use crate::testdata::{bytes, digest, fixtures, path, uints};
+
+        let tree =
+            |n: u64| Tree::build_vector_commitment(&(0..n).map(TestElement).collect::<Vec<_>>());
+        for vc in fixtures("vc") {
+            let n: u64 = vc[0].parse().unwrap();
+            assert_eq!(
+                tree(n).root(),
+                Some(digest(&vc[1])),
+                "root of {} elements",
+                n
+            );
+        }
+
+        for proof in fixtures("vcproof") {
+            let n: u64 = proof[0].parse().unwrap();
+            let idxs = uints(&proof[1]);
+            let want = Proof {
+                path: path(&proof[3]),
+                tree_depth: proof[2].parse().unwrap(),
+            };
+            let tree = tree(n);
+            assert_eq!(
+                tree.prove(&idxs).unwrap(),
+                want,
+                "{} elements, {:?}",
+                n,
+                idxs
+            );
+
+            let proven: Vec<_> = idxs.iter().map(|&i| (i, TestElement(i))).collect();
+            verify_vector_commitment(&tree.root().unwrap(), &proven, &want).unwrap();
+        }
+
+        for single in fixtures("single") {
+            let (n, i): (u64, u64) = (single[0].parse().unwrap(), single[1].parse().unwrap());
+            let want = SingleLeafProof(Proof {
+                path: path(&single[3]),
+                tree_depth: single[2].parse().unwrap(),
+            });
+            let proof = tree(n).prove_single_leaf(i).unwrap();
+            assert_eq!(proof, want, "{} elements, leaf {}", n, i);
+            assert_eq!(
+                proof.fixed_length_hashable_representation(),
+                bytes(&single[4]),
+                "{} elements, leaf {}",
+                n,
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn single_leaf_proof() {
+        let elems: Vec<_> = (0..5).map(TestElement).collect();
+        let tree = Tree::build_vector_commitment(&elems);
+        let proof = tree.prove_single_leaf(4).unwrap();
+        verify_vector_commitment(&tree.root().unwrap(), &[(4, TestElement(4))], &proof.0).unwrap();
+
+        let repr = proof.fixed_length_hashable_representation();
+        assert_eq!(repr.len(), 1 + 16 * 64);
+        assert_eq!(repr[0], 3);
+        assert!(repr[1..1 + 13 * 64].iter().all(|&b| b == 0));
+        proof.0.path.iter().enumerate().for_each(|(i, sibling)| {
+            let at = 1 + (13 + i) * 64;
+            assert_eq!(&repr[at..at + 64], &sibling.unwrap()[..]);
+        });
+
+        // Missing siblings are encoded as zeros.
+        let tree = Tree::build(&elems);
+        let repr = tree
+            .prove_single_leaf(4)
+            .unwrap()
+            .fixed_length_hashable_representation();
+        assert_eq!(repr.len(), 1 + 16 * 64);
+        assert!(repr[1..1 + 15 * 64].iter().all(|&b| b == 0));
+    }
+}
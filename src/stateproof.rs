@@ -0,0 +1,537 @@
+//! Commitments of Algorand state proofs to their participants and signatures.
+//!
+//! The participants of a state proof and its signature slots are committed to with vector commitment trees
+//! (see [`merkle`](crate::merkle)) hashed with sumhash512, as in go-algorand's `stateproof` package. The
+//! types of this module mirror go-algorand's, with their canonical msgpack encoding and their hashable
+//! representation.
+//!
+//! go-algorand hashes a Falcon signature in its constant-time (CT) format, which it derives from the
+//! compressed signature with the Falcon library. This crate doesn't implement Falcon, so the CT format of a
+//! signature has to be provided along with its compressed format.
+use std::borrow::Cow;
+
+use crate::hashid::{HashID, Hashable, STATE_PROOF_PART, STATE_PROOF_SIG};
+use crate::merkle::{Digest, SingleLeafProof, Tree};
+
+/// MERKLE_SIGNATURE_SCHEME_ROOT_SIZE is the size of the commitment of a participant to its keys.
+pub const MERKLE_SIGNATURE_SCHEME_ROOT_SIZE: usize = 64;
+
+/// FALCON_PUBLIC_KEY_SIZE is the size of a Falcon-1024 public key.
+pub const FALCON_PUBLIC_KEY_SIZE: usize = 1793;
+
+// CRYPTO_PRIMITIVES_ID identifies Falcon with sumhash512 in the hashable representation of a signature.
+const CRYPTO_PRIMITIVES_ID: u16 = 0;
+
+// SUMHASH_HASH_TYPE is the value of go-algorand's crypto.Sumhash hash type.
+const SUMHASH_HASH_TYPE: u64 = 1;
+
+/// Verifier is the commitment of a participant to its Falcon keys, valid for key_lifetime rounds each.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Verifier {
+    /// commitment is the root of the vector commitment tree of the participant's keys.
+    pub commitment: [u8; MERKLE_SIGNATURE_SCHEME_ROOT_SIZE],
+    /// key_lifetime is the number of rounds each key is valid for.
+    pub key_lifetime: u64,
+}
+
+/// Participant is an account taking part in a state proof.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Participant {
+    /// pk is the commitment to the keys of the participant.
+    pub pk: Verifier,
+    /// weight is the balance of the participant, in microalgos.
+    pub weight: u64,
+}
+
+/// MerkleSignature is a Falcon signature along with the proof that its key is committed to by a Verifier.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerkleSignature {
+    /// signature is the Falcon signature in its compressed format, as encoded in a state proof.
+    pub signature: Vec<u8>,
+    /// ct_signature is the signature in the Falcon CT format, which is hashed instead of the compressed one.
+    /// It isn't part of the msgpack encoding.
+    pub ct_signature: Vec<u8>,
+    /// vector_commitment_index is the position of the verifying key in the vector commitment of the keys.
+    pub vector_commitment_index: u64,
+    /// proof is the proof of the verifying key in the vector commitment of the keys.
+    pub proof: SingleLeafProof,
+    /// verifying_key is the Falcon public key.
+    pub verifying_key: [u8; FALCON_PUBLIC_KEY_SIZE],
+}
+
+impl Default for MerkleSignature {
+    // The zero signature, as in the slots of the participants who didn't sign.
+    fn default() -> Self {
+        MerkleSignature {
+            signature: Vec::new(),
+            ct_signature: Vec::new(),
+            vector_commitment_index: 0,
+            proof: SingleLeafProof::default(),
+            verifying_key: [0; FALCON_PUBLIC_KEY_SIZE],
+        }
+    }
+}
+
+/// SigslotCommit is the committed part of a signature slot.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SigslotCommit {
+    /// sig is the signature of the participant.
+    pub sig: MerkleSignature,
+    /// l is the total weight of the signatures in the lower-numbered slots.
+    pub l: u64,
+}
+
+/// SignatureSlot is the slot of a participant in a state proof, empty if the participant didn't sign.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignatureSlot {
+    /// weight is the weight of the participant, zero for an empty slot.
+    pub weight: u64,
+    /// commit is the committed part of the slot, with the zero signature for an empty slot.
+    pub commit: SigslotCommit,
+}
+
+/// participant_commitment returns the root of the vector commitment tree of participants, or None if there
+/// are none.
+pub fn participant_commitment(participants: &[Participant]) -> Option<Digest> {
+    Tree::build_vector_commitment(participants).root()
+}
+
+/// signature_commitment returns the root of the vector commitment tree of the signature slots, or None if
+/// there are none.
+pub fn signature_commitment(slots: &[SignatureSlot]) -> Option<Digest> {
+    Tree::build_vector_commitment(slots).root()
+}
+
+impl Hashable for Participant {
+    // The weight and the key lifetime as little-endian u64, followed by the commitment.
+    fn to_be_hashed(&self) -> (HashID, Cow<'_, [u8]>) {
+        let mut data = Vec::with_capacity(16 + MERKLE_SIGNATURE_SCHEME_ROOT_SIZE);
+        data.extend_from_slice(&self.weight.to_le_bytes());
+        data.extend_from_slice(&self.pk.key_lifetime.to_le_bytes());
+        data.extend_from_slice(&self.pk.commitment);
+        (STATE_PROOF_PART, Cow::Owned(data))
+    }
+}
+
+impl MerkleSignature {
+    /// fixed_length_hashable_representation returns the scheme identifier as a little-endian u16, the CT
+    /// signature, the verifying key, the vector commitment index as a little-endian u64 and the fixed length
+    /// representation of the proof, as go-algorand's `GetFixedLengthHashableRepresentation`.
+    pub fn fixed_length_hashable_representation(&self) -> Vec<u8> {
+        let proof = self.proof.fixed_length_hashable_representation();
+        let mut out = Vec::with_capacity(
+            2 + self.ct_signature.len() + FALCON_PUBLIC_KEY_SIZE + 8 + proof.len(),
+        );
+        out.extend_from_slice(&CRYPTO_PRIMITIVES_ID.to_le_bytes());
+        out.extend_from_slice(&self.ct_signature);
+        out.extend_from_slice(&self.verifying_key);
+        out.extend_from_slice(&self.vector_commitment_index.to_le_bytes());
+        out.extend_from_slice(&proof);
+        out
+    }
+
+    /// is_zero returns whether the signature is the zero signature of an empty slot, whose proof has no hash
+    /// factory.
+    pub fn is_zero(&self) -> bool {
+        *self == MerkleSignature::default()
+    }
+
+    /// to_msgpack returns the canonical msgpack encoding of the signature, an empty map for the zero signature.
+    pub fn to_msgpack(&self) -> Vec<u8> {
+        let mut e = Encoder::default();
+        self.encode(&mut e);
+        e.buf
+    }
+
+    fn encode(&self, e: &mut Encoder) {
+        if self.is_zero() {
+            e.map(0);
+            return;
+        }
+        let proof = &self.proof.0;
+        // The proof of a signature isn't empty, since its hash factory is sumhash.
+        let fields = [
+            self.vector_commitment_index != 0,
+            !self.signature.is_empty(),
+            self.verifying_key.iter().any(|&b| b != 0),
+        ];
+        e.map(1 + fields.iter().filter(|&&f| f).count());
+        if fields[0] {
+            e.str("idx");
+            e.uint(self.vector_commitment_index);
+        }
+
+        // The fields of the embedded merklearray.Proof are inlined.
+        let proof_fields = [!proof.path.is_empty(), proof.tree_depth != 0];
+        e.str("prf");
+        e.map(1 + proof_fields.iter().filter(|&&f| f).count());
+        e.str("hsh");
+        e.map(1);
+        e.str("t");
+        e.uint(SUMHASH_HASH_TYPE);
+        if proof_fields[0] {
+            e.str("pth");
+            e.array(proof.path.len());
+            proof
+                .path
+                .iter()
+                .for_each(|sibling| e.bin(sibling.as_ref().map_or(&[][..], |s| &s[..])));
+        }
+        if proof_fields[1] {
+            e.str("td");
+            e.uint(proof.tree_depth as u64);
+        }
+
+        if fields[1] {
+            e.str("sig");
+            e.bin(&self.signature);
+        }
+        if fields[2] {
+            e.str("vkey");
+            e.map(1);
+            e.str("k");
+            e.bin(&self.verifying_key);
+        }
+    }
+}
+
+impl Participant {
+    /// to_msgpack returns the canonical msgpack encoding of the participant.
+    pub fn to_msgpack(&self) -> Vec<u8> {
+        let mut e = Encoder::default();
+        let commitment = self.pk.commitment.iter().any(|&b| b != 0);
+        let pk = [commitment, self.pk.key_lifetime != 0];
+        let fields = [pk.contains(&true), self.weight != 0];
+        e.map(fields.iter().filter(|&&f| f).count());
+        if fields[0] {
+            e.str("p");
+            e.map(pk.iter().filter(|&&f| f).count());
+            if pk[0] {
+                e.str("cmt");
+                e.bin(&self.pk.commitment);
+            }
+            if pk[1] {
+                e.str("lf");
+                e.uint(self.pk.key_lifetime);
+            }
+        }
+        if fields[1] {
+            e.str("w");
+            e.uint(self.weight);
+        }
+        e.buf
+    }
+}
+
+impl SigslotCommit {
+    /// to_msgpack returns the canonical msgpack encoding of the committed part of a slot.
+    pub fn to_msgpack(&self) -> Vec<u8> {
+        // The zero signature of an empty slot is omitted, its proof having no hash factory. Other signatures
+        // aren't empty, since their proof's hash factory is sumhash.
+        let fields = [self.l != 0, !self.sig.is_zero()];
+        let mut e = Encoder::default();
+        e.map(fields.iter().filter(|&&f| f).count());
+        if fields[0] {
+            e.str("l");
+            e.uint(self.l);
+        }
+        if fields[1] {
+            e.str("s");
+            self.sig.encode(&mut e);
+        }
+        e.buf
+    }
+}
+
+impl Hashable for SignatureSlot {
+    // The L of the slot as a little-endian u64, followed by the representation of the signature.
+    // Empty slots, whose signature is the zero signature, are hashed as no data whatever their weight, as
+    // go-algorand checks the signature alone.
+    fn to_be_hashed(&self) -> (HashID, Cow<'_, [u8]>) {
+        if self.commit.sig.is_zero() {
+            return (STATE_PROOF_SIG, Cow::Borrowed(&[]));
+        }
+        let sig = self.commit.sig.fixed_length_hashable_representation();
+        let mut data = Vec::with_capacity(8 + sig.len());
+        data.extend_from_slice(&self.commit.l.to_le_bytes());
+        data.extend_from_slice(&sig);
+        (STATE_PROOF_SIG, Cow::Owned(data))
+    }
+}
+
+// Encoder writes the msgpack encoding of values with their smallest representation.
+#[derive(Default)]
+struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    fn map(&mut self, len: usize) {
+        self.header(len, 0x80, 0xde);
+    }
+
+    fn array(&mut self, len: usize) {
+        self.header(len, 0x90, 0xdc);
+    }
+
+    // header writes the length of a map or an array, in its fix or 16/32 bits form.
+    fn header(&mut self, len: usize, fix: u8, tag16: u8) {
+        match len {
+            0..=15 => self.buf.push(fix | len as u8),
+            16..=0xffff => {
+                self.buf.push(tag16);
+                self.buf.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            _ => {
+                self.buf.push(tag16 + 1);
+                self.buf.extend_from_slice(&(len as u32).to_be_bytes());
+            }
+        }
+    }
+
+    // str writes a short string, the field names being all shorter than 32 bytes.
+    fn str(&mut self, s: &str) {
+        self.buf.push(0xa0 | s.len() as u8);
+        self.buf.extend_from_slice(s.as_bytes());
+    }
+
+    fn uint(&mut self, v: u64) {
+        match v {
+            0..=0x7f => self.buf.push(v as u8),
+            0x80..=0xff => self.buf.extend_from_slice(&[0xcc, v as u8]),
+            0x100..=0xffff => {
+                self.buf.push(0xcd);
+                self.buf.extend_from_slice(&(v as u16).to_be_bytes());
+            }
+            0x10000..=0xffff_ffff => {
+                self.buf.push(0xce);
+                self.buf.extend_from_slice(&(v as u32).to_be_bytes());
+            }
+            _ => {
+                self.buf.push(0xcf);
+                self.buf.extend_from_slice(&v.to_be_bytes());
+            }
+        }
+    }
+
+    fn bin(&mut self, b: &[u8]) {
+        match b.len() {
+            0..=0xff => self.buf.extend_from_slice(&[0xc4, b.len() as u8]),
+            0x100..=0xffff => {
+                self.buf.push(0xc5);
+                self.buf.extend_from_slice(&(b.len() as u16).to_be_bytes());
+            }
+            _ => {
+                self.buf.push(0xc6);
+                self.buf.extend_from_slice(&(b.len() as u32).to_be_bytes());
+            }
+        }
+        self.buf.extend_from_slice(b);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::merkle::Proof;
+    use crate::sumhash512;
+
+    // The commitments are recomputed from the go-algorand construction with the one-shot sumhash512, and the
+    // go_algorand test checks the reveals of a mainnet state proof.
+
+    fn participant(i: u8) -> Participant {
+        Participant {
+            pk: Verifier {
+                commitment: [i; 64],
+                key_lifetime: 256,
+            },
+            weight: 1_000_000 * i as u64,
+        }
+    }
+
+    fn slot(i: u8) -> SignatureSlot {
+        SignatureSlot {
+            weight: i as u64,
+            commit: SigslotCommit {
+                sig: MerkleSignature {
+                    signature: vec![i; 10],
+                    ct_signature: vec![i; 20],
+                    vector_commitment_index: 3,
+                    proof: SingleLeafProof(Proof {
+                        path: vec![Some([i; 64]), None],
+                        tree_depth: 2,
+                    }),
+                    verifying_key: [i; FALCON_PUBLIC_KEY_SIZE],
+                },
+                l: 10 * i as u64,
+            },
+        }
+    }
+
+    #[test]
+    fn participants() {
+        let p = participant(7);
+        let mut data = b"spp".to_vec();
+        data.extend_from_slice(&7_000_000u64.to_le_bytes());
+        data.extend_from_slice(&256u64.to_le_bytes());
+        data.extend_from_slice(&[7; 64]);
+        let leaf = sumhash512(&data);
+        assert_eq!(participant_commitment(std::slice::from_ref(&p)), Some(leaf));
+
+        let participants: Vec<_> = (1..4).map(participant).collect();
+        let leaves: Vec<_> = participants.iter().map(crate::hashid::hash_obj).collect();
+        let node = |l: &Digest, r: &Digest| sumhash512([&b"MA"[..], l, r].concat());
+        let want = node(
+            &node(&leaves[0], &leaves[2]),
+            &node(&leaves[1], &sumhash512("BL")),
+        );
+        assert_eq!(participant_commitment(&participants), Some(want));
+        assert_eq!(participant_commitment(&[]), None);
+
+        let mut want = vec![0x82, 0xa1, b'p', 0x82, 0xa3, b'c', b'm', b't', 0xc4, 0x40];
+        want.extend_from_slice(&[7; 64]);
+        want.extend_from_slice(&[0xa2, b'l', b'f', 0xcd, 0x01, 0x00]);
+        want.extend_from_slice(&[0xa1, b'w', 0xce, 0x00, 0x6a, 0xcf, 0xc0]);
+        assert_eq!(p.to_msgpack(), want);
+
+        let empty = Participant {
+            pk: Verifier {
+                commitment: [0; 64],
+                key_lifetime: 0,
+            },
+            weight: 0,
+        };
+        assert_eq!(empty.to_msgpack(), vec![0x80]);
+    }
+
+    #[test]
+    #[ignore = "needs the fixtures generated by testdata/go-algorand"]
+    fn go_algorand_state_proof() {
+        use crate::merkle::verify_vector_commitment;
+        use crate::testdata::{bytes, digest, fixtures, path};
+
+        let proof = |depth: &str, p: &str| Proof {
+            path: path(p),
+            tree_depth: depth.parse().unwrap(),
+        };
+        let reveals = fixtures("reveal");
+        for sp in fixtures("stateproof") {
+            let (voters, sig_commit) = (digest(&sp[1]), digest(&sp[2]));
+
+            // The reveals of a state proof start with the last round it attests.
+            let (mut participants, mut slots) = (Vec::new(), Vec::new());
+            for reveal in reveals.iter().filter(|reveal| reveal[0] == sp[0]) {
+                let reveal = &reveal[1..];
+                let pos: u64 = reveal[0].parse().unwrap();
+                let participant = Participant {
+                    pk: Verifier {
+                        commitment: bytes(&reveal[2]).try_into().unwrap(),
+                        key_lifetime: reveal[3].parse().unwrap(),
+                    },
+                    weight: reveal[1].parse().unwrap(),
+                };
+                let slot = SignatureSlot {
+                    weight: participant.weight,
+                    commit: SigslotCommit {
+                        sig: MerkleSignature {
+                            signature: bytes(&reveal[5]),
+                            ct_signature: bytes(&reveal[6]),
+                            vector_commitment_index: reveal[7].parse().unwrap(),
+                            proof: SingleLeafProof(proof(&reveal[8], &reveal[9])),
+                            verifying_key: bytes(&reveal[10]).try_into().unwrap(),
+                        },
+                        l: reveal[4].parse().unwrap(),
+                    },
+                };
+                assert_eq!(
+                    participant.to_msgpack(),
+                    bytes(&reveal[11]),
+                    "participant {}",
+                    pos
+                );
+                assert_eq!(slot.commit.to_msgpack(), bytes(&reveal[12]), "slot {}", pos);
+                participants.push((pos, participant));
+                slots.push((pos, slot));
+            }
+
+            assert!(!participants.is_empty(), "no reveals of round {}", sp[0]);
+
+            // The reveals are proven against the commitments of the state proof.
+            verify_vector_commitment(&voters, &participants, &proof(&sp[3], &sp[4])).unwrap();
+            verify_vector_commitment(&sig_commit, &slots, &proof(&sp[5], &sp[6])).unwrap();
+        }
+    }
+
+    #[test]
+    fn signatures() {
+        let s = slot(5);
+        let mut data = b"sps".to_vec();
+        data.extend_from_slice(&50u64.to_le_bytes());
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data.extend_from_slice(&[5; 20]);
+        data.extend_from_slice(&[5; FALCON_PUBLIC_KEY_SIZE]);
+        data.extend_from_slice(&3u64.to_le_bytes());
+        data.push(2);
+        data.extend_from_slice(&[0; 14 * 64]);
+        data.extend_from_slice(&[5; 64]);
+        data.extend_from_slice(&[0; 64]);
+        assert_eq!(
+            signature_commitment(std::slice::from_ref(&s)),
+            Some(sumhash512(&data))
+        );
+
+        // Empty slots are hashed as their prefix alone.
+        let mut empty = slot(5);
+        empty.weight = 0;
+        empty.commit.sig = MerkleSignature::default();
+        let slots = vec![s.clone(), empty];
+        let node = sumhash512([&b"MA"[..], &sumhash512(&data), &sumhash512("sps")].concat());
+        assert_eq!(signature_commitment(&slots), Some(node));
+
+        // A slot is empty because of its signature, not its weight.
+        let mut unweighted = s.clone();
+        unweighted.weight = 0;
+        assert_eq!(
+            signature_commitment(&[unweighted]),
+            signature_commitment(std::slice::from_ref(&s))
+        );
+
+        let mut want = vec![0x82, 0xa1, b'l', 0x32, 0xa1, b's', 0x84];
+        want.extend_from_slice(&[0xa3, b'i', b'd', b'x', 0x03]);
+        want.extend_from_slice(&[0xa3, b'p', b'r', b'f', 0x83]);
+        want.extend_from_slice(&[0xa3, b'h', b's', b'h', 0x81, 0xa1, b't', 0x01]);
+        want.extend_from_slice(&[0xa3, b'p', b't', b'h', 0x92, 0xc4, 0x40]);
+        want.extend_from_slice(&[5; 64]);
+        want.extend_from_slice(&[0xc4, 0x00]);
+        want.extend_from_slice(&[0xa2, b't', b'd', 0x02]);
+        want.extend_from_slice(&[0xa3, b's', b'i', b'g', 0xc4, 0x0a]);
+        want.extend_from_slice(&[5; 10]);
+        want.extend_from_slice(&[
+            0xa4, b'v', b'k', b'e', b'y', 0x81, 0xa1, b'k', 0xc5, 0x07, 0x01,
+        ]);
+        want.extend_from_slice(&[5; FALCON_PUBLIC_KEY_SIZE]);
+        assert_eq!(s.commit.to_msgpack(), want);
+
+        // The zero signature of an empty slot is omitted, and hashed as no data like any empty slot.
+        let empty = SignatureSlot {
+            weight: 0,
+            commit: SigslotCommit {
+                sig: MerkleSignature::default(),
+                l: 50,
+            },
+        };
+        assert!(empty.commit.sig.is_zero() && !s.commit.sig.is_zero());
+        assert_eq!(empty.commit.to_msgpack(), vec![0x81, 0xa1, b'l', 0x32]);
+        assert_eq!(empty.commit.sig.to_msgpack(), vec![0x80]);
+        let zero = SigslotCommit {
+            sig: MerkleSignature::default(),
+            l: 0,
+        };
+        assert_eq!(zero.to_msgpack(), vec![0x80]);
+        assert_eq!(
+            signature_commitment(&[s, empty]),
+            Some(node),
+            "empty slot hashed differently"
+        );
+    }
+}
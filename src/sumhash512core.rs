@@ -1,15 +1,15 @@
 use digest::{
-    block_buffer::Eager,
-    core_api::{Buffer, BufferKindUser, FixedOutputCore, UpdateCore},
-    crypto_common::{Block, BlockSizeUser},
-    typenum::U64,
-    HashMarker, Output, OutputSizeUser, Reset,
+    core_api::CoreWrapper,
+    typenum::{U1024, U8},
+    FixedOutput, Update,
 };
-
-use byteorder::{ByteOrder, LittleEndian};
+#[cfg(not(feature = "embedded-table"))]
 use once_cell::sync::Lazy;
 
-use crate::compress::{Compressor, LookupTable, Matrix};
+use crate::compress::LookupTable;
+#[cfg(not(feature = "embedded-table"))]
+use crate::compress::Matrix;
+use crate::sumhashcore::SumhashCore;
 
 /// The size in bytes of the sumhash checksum.
 pub const DIGEST_SIZE: usize = 64;
@@ -17,111 +17,86 @@ pub const DIGEST_SIZE: usize = 64;
 /// Block size, in bytes, of the sumhash hash function.
 pub const DIGEST_BLOCK_SIZE: usize = 64;
 
-/// AlgorandSumhash512 is an Algorand instance of Sumhash512Core with a lookup table as compressor.
-pub type AlgorandSumhash512Core = Sumhash512Core<LookupTable>;
+/// Sumhash512Core is a sumhash core with 512 bit output (n=8, m=1024), absorbing 64 byte blocks.
+///
+/// The compressor can be owned (e.g. `Sumhash512Core<Matrix>`), shared (`Sumhash512Core<Arc<Matrix>>`)
+/// or borrowed (`Sumhash512Core<&'a Matrix>`).
+pub type Sumhash512Core<C> = SumhashCore<C, U8, U1024>;
 
-impl AlgorandSumhash512Core {
-    /// new_with_salt returns a Sumhash512 with salt.
-    pub fn new_with_salt(salt: [u8; DIGEST_BLOCK_SIZE]) -> Self {
-        let mut s = Self {
-            salt: Some(salt),
-            ..Default::default()
-        };
-        s.compress_block(&[0; DIGEST_SIZE]);
-        s
-    }
+/// AlgorandSumhash512 is an Algorand instance of Sumhash512Core with a lookup table as compressor.
+/// The lookup table is shared by every instance, so creating a core doesn't copy it.
+pub type AlgorandSumhash512Core = Sumhash512Core<&'static LookupTable<'static>>;
+
+/// Sumhash512 is the Algorand instance of sumhash512, ready to hash messages.
+pub type Sumhash512 = CoreWrapper<AlgorandSumhash512Core>;
+
+/// Sumhash512Salted returns a Sumhash512 with salt.
+/// It's named like the type of the hasher it constructs, as `Sumhash512` can't have associated functions
+/// of its own.
+#[allow(non_snake_case)]
+pub fn Sumhash512Salted(salt: [u8; DIGEST_BLOCK_SIZE]) -> Sumhash512 {
+    Sumhash512::from_core(AlgorandSumhash512Core::new_with_salt(salt))
 }
 
-static LOOKUP_TABLE: Lazy<LookupTable> =
-    Lazy::new(|| Matrix::random_from_seed("Algorand".as_bytes(), 8, 1024).lookup_table());
-
-impl Default for AlgorandSumhash512Core {
-    fn default() -> Self {
-        Sumhash512Core::new(&LOOKUP_TABLE, None)
-    }
+/// sumhash512 returns the Sumhash512 digest of data.
+pub fn sumhash512(data: impl AsRef<[u8]>) -> [u8; DIGEST_SIZE] {
+    finalize(Sumhash512::default(), data.as_ref())
 }
 
-/// Sumhash512Core returns a core implementation for sumhash cryptographic hash function.
-pub struct Sumhash512Core<C: Compressor + 'static> {
-    c: &'static C,
-    h: [u8; DIGEST_SIZE], // hash chain (from last compression, or IV)
-    len: u64,
-    salt: Option<[u8; DIGEST_BLOCK_SIZE]>,
+/// sumhash512_salted returns the Sumhash512 digest of data with salt.
+pub fn sumhash512_salted(
+    salt: [u8; DIGEST_BLOCK_SIZE],
+    data: impl AsRef<[u8]>,
+) -> [u8; DIGEST_SIZE] {
+    finalize(Sumhash512Salted(salt), data.as_ref())
 }
 
-impl<C: Compressor> Sumhash512Core<C> {
-    fn new(c: &'static C, salt: Option<[u8; DIGEST_BLOCK_SIZE]>) -> Self {
-        Self {
-            c,
-            h: [0; DIGEST_SIZE],
-            salt,
-            len: 0,
-        }
-    }
-
-    fn compress_block(&mut self, data: &[u8]) {
-        let mut cin = [0; DIGEST_BLOCK_SIZE * 2];
-        self.len += data.len() as u64;
-
-        cin[0..DIGEST_BLOCK_SIZE].clone_from_slice(&self.h);
-        match self.salt {
-            Some(ref salt) => cin[DIGEST_BLOCK_SIZE..]
-                .iter_mut()
-                .enumerate()
-                .for_each(|(i, val)| *val = data[i] ^ salt[i]),
-            None => cin[DIGEST_BLOCK_SIZE..].clone_from_slice(data),
-        }
-
-        self.c.compress(&mut self.h, &cin);
-    }
+fn finalize(mut h: Sumhash512, data: &[u8]) -> [u8; DIGEST_SIZE] {
+    h.update(data);
+    h.finalize_fixed().into()
 }
 
-impl<C: Compressor> Reset for Sumhash512Core<C> {
-    fn reset(&mut self) {
-        self.h = [0; DIGEST_SIZE];
-        self.len = 0;
-        if self.salt.is_some() {
-            // Write an initial block of zeros, effectively
-            // prepending the salt to the input.
-            self.compress_block(&[0; DIGEST_SIZE]);
-        }
+impl AlgorandSumhash512Core {
+    /// new_with_salt returns a Sumhash512 with salt.
+    pub fn new_with_salt(salt: [u8; DIGEST_BLOCK_SIZE]) -> Self {
+        Self::new_unchecked(&LOOKUP_TABLE, Some(salt.into()))
     }
 }
 
-impl<C: Compressor> HashMarker for Sumhash512Core<C> {}
-
-impl<C: Compressor> BlockSizeUser for Sumhash512Core<C> {
-    type BlockSize = U64;
-}
-
-impl<C: Compressor> BufferKindUser for Sumhash512Core<C> {
-    type BufferKind = Eager;
-}
-
-impl<C: Compressor> OutputSizeUser for Sumhash512Core<C> {
-    type OutputSize = U64;
-}
-
-impl<C: Compressor> FixedOutputCore for Sumhash512Core<C> {
-    fn finalize_fixed_core(&mut self, buffer: &mut Buffer<Self>, out: &mut Output<Self>) {
-        let bitlen = (self.len + buffer.get_pos() as u64) << 3; // number of input bits written
-        let mut tmp = [0; 16];
-        LittleEndian::write_u64(&mut tmp, bitlen);
-        buffer.digest_pad(0x01, &tmp, |a| self.compress_block(a));
+#[cfg(not(feature = "embedded-table"))]
+static LOOKUP_TABLE: Lazy<LookupTable<'static>> =
+    Lazy::new(|| Matrix::random_from_seed("Algorand".as_bytes(), 8, 1024).lookup_table());
 
-        out.copy_from_slice(&self.h);
-    }
-}
+// The Algorand lookup table generated by the build script, which needs no initialization.
+#[cfg(feature = "embedded-table")]
+static LOOKUP_TABLE: LookupTable<'static> = {
+    // SAFETY: the build script writes the 128 x 256 x 8 sums as words in the target byte order,
+    // and every bit pattern is a valid u64.
+    static SUMS: [u64; 128 * 256 * 8] = unsafe {
+        std::mem::transmute(*include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/algorand_lookup_table.bin"
+        )))
+    };
+    LookupTable::borrowed(
+        &SUMS,
+        8,
+        Some(*include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/algorand_seed_fingerprint.bin"
+        ))),
+    )
+};
 
-impl<C: Compressor> UpdateCore for Sumhash512Core<C> {
-    fn update_blocks(&mut self, blocks: &[Block<Self>]) {
-        for b in blocks {
-            self.compress_block(b)
-        }
+impl Default for AlgorandSumhash512Core {
+    fn default() -> Self {
+        Self::new_unchecked(&LOOKUP_TABLE, None)
     }
 }
 
 #[cfg(test)]
+// The test module is kept as published, predating the lints enforced on the rest of the crate.
+#[allow(missing_docs, clippy::needless_borrows_for_generic_args)]
 pub mod test {
     use std::io::Write;
 
@@ -132,6 +107,10 @@ pub mod test {
         Shake256,
     };
 
+    use crate::compress::Matrix;
+    use digest::Reset;
+    use std::sync::Arc;
+
     struct TestElement {
         input: &'static str,
         output: &'static str,
@@ -219,6 +198,69 @@ pub mod test {
         assert_eq!(sum, expected_sum, "got {}, want {}", sum, expected_sum);
     }
 
+    #[test]
+    fn sumhash512_compressor_ownership() {
+        let input = "You must be the change you wish to see in the world. -Mahatma Gandhi";
+        let expected_sum = TEST_VECTOR[5].output;
+        let a = Matrix::random_from_seed("Algorand".as_bytes(), 8, 1024);
+
+        let mut h = CoreWrapper::from_core(Sumhash512Core::from_ref(&a).unwrap());
+        h.update(input.as_bytes());
+        let sum = hex::encode(h.finalize_fixed());
+        assert_eq!(
+            sum, expected_sum,
+            "borrowed: got {}, want {}",
+            sum, expected_sum
+        );
+
+        let mut h =
+            CoreWrapper::from_core(Sumhash512Core::from_arc(Arc::new(a.lookup_table())).unwrap());
+        h.update(input.as_bytes());
+        let sum = hex::encode(h.finalize_fixed());
+        assert_eq!(
+            sum, expected_sum,
+            "shared: got {}, want {}",
+            sum, expected_sum
+        );
+
+        let mut h = CoreWrapper::from_core(Sumhash512Core::new(a).unwrap());
+        h.update(input.as_bytes());
+        let sum = hex::encode(h.finalize_fixed());
+        assert_eq!(
+            sum, expected_sum,
+            "owned: got {}, want {}",
+            sum, expected_sum
+        );
+    }
+
+    #[test]
+    fn sumhash512_custom_compressor() {
+        let mut salt = [0; 64];
+        let mut v = Shake256::default();
+        v.write_all("sumhash salt".as_bytes()).unwrap();
+        v.finalize_xof().read(&mut salt);
+
+        let a = Matrix::random_from_seed("Algorand".as_bytes(), 8, 1024);
+        let mut h = CoreWrapper::from_core(Sumhash512Core::with_salt(a, salt).unwrap());
+        h.update("sumhash input".as_bytes());
+
+        let mut want = CoreWrapper::from_core(AlgorandSumhash512Core::new_with_salt(salt));
+        want.update("sumhash input".as_bytes());
+        assert_eq!(h.finalize_fixed(), want.finalize_fixed());
+
+        let a = Matrix::random_from_seed("Algorand".as_bytes(), 8, 512);
+        assert!(
+            Sumhash512Core::new(a).is_err(),
+            "input size must be validated"
+        );
+
+        let a = Matrix::random_from_seed("Algorand".as_bytes(), 4, 1024);
+        assert!(
+            Sumhash512Core::new(a).is_err(),
+            "output size must be validated"
+        );
+    }
+
     #[test]
     fn sumhash512_reset() {
         let mut input = [0; 6000];
@@ -242,4 +284,86 @@ pub mod test {
         let expected_sum = "43dc59ca43da473a3976a952f1c33a2b284bf858894ef7354b8fc0bae02b966391070230dd23e0713eaf012f7ad525f198341000733aa87a904f7053ce1a43c6";
         assert_eq!(sum, expected_sum, "got {}, want {}", sum, expected_sum);
     }
+
+    #[test]
+    fn sumhash512_xof() {
+        let mut h = CoreWrapper::<AlgorandSumhash512Core>::default();
+        h.update(TEST_VECTOR[3].input.as_bytes());
+        let mut out = [0u8; 200];
+        h.finalize_xof_into(&mut out);
+        assert_ne!(hex::encode(&out[..64]), TEST_VECTOR[3].output);
+    }
+
+    #[test]
+    fn sumhash512_one_shot() {
+        TEST_VECTOR.iter().for_each(|element| {
+            assert_eq!(
+                hex::encode(super::sumhash512(element.input)),
+                element.output
+            );
+        });
+
+        let mut input = [0; 6000];
+        let mut v = Shake256::default();
+        v.write_all("sumhash input".as_bytes()).unwrap();
+        v.finalize_xof().read(&mut input);
+        let mut salt = [0; 64];
+        v = Shake256::default();
+        v.write_all("sumhash salt".as_bytes()).unwrap();
+        v.finalize_xof().read(&mut salt);
+
+        let sum = hex::encode(sumhash512_salted(salt, input));
+        let expected_sum = "c9be08eed13218c30f8a673f7694711d87dfec9c7b0cb1c8e18bf68420d4682530e45c1cd5d886b1c6ab44214161f06e091b0150f28374d6b5ca0c37efc2bca7";
+        assert_eq!(sum, expected_sum, "got {}, want {}", sum, expected_sum);
+
+        let mut h = Sumhash512Salted(salt);
+        h.update(&input);
+        assert_eq!(hex::encode(h.finalize_fixed()), expected_sum);
+    }
+
+    #[test]
+    fn sumhash512_fork() {
+        let prefix = "You must be the change you wish to see in the world.";
+        let suffixes = [
+            "",
+            " -Mahatma Gandhi",
+            " And other quotes, long enough to span a block.",
+        ];
+        for h in [
+            CoreWrapper::<AlgorandSumhash512Core>::default(),
+            CoreWrapper::from_core(AlgorandSumhash512Core::new_with_salt([0x42; 64])),
+        ] {
+            let mut h = h;
+            h.update(prefix.as_bytes());
+            for suffix in suffixes {
+                let mut fork = h.clone();
+                fork.update(suffix.as_bytes());
+
+                let mut want = h.clone();
+                want.reset();
+                want.update(format!("{}{}", prefix, suffix).as_bytes());
+                assert_eq!(fork.finalize_fixed(), want.finalize_fixed());
+            }
+        }
+    }
+
+    #[test]
+    fn sumhash512_debug() {
+        let salt = [0x42; 64];
+        let h = CoreWrapper::from_core(AlgorandSumhash512Core::new_with_salt(salt));
+        assert_eq!(format!("{:?}", h), "Sumhash512 { .. }");
+        let debug = format!("{:?}", h.decompose().0);
+        assert!(debug.contains("salted: true"), "{}", debug);
+        assert!(!debug.contains("42") && !debug.contains("66"), "{}", debug);
+    }
+
+    #[test]
+    fn lookup_table() {
+        let a = Matrix::random_from_seed("Algorand".as_bytes(), 8, 1024);
+        let mut want = Vec::new();
+        a.lookup_table().write_to(&mut want).unwrap();
+        let mut got = Vec::new();
+        LOOKUP_TABLE.write_to(&mut got).unwrap();
+        assert!(got == want, "Algorand lookup table mismatch");
+    }
 }
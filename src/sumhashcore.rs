@@ -0,0 +1,551 @@
+use digest::{
+    block_buffer::Eager,
+    core_api::{
+        Buffer, BufferKindUser, ExtendableOutputCore, FixedOutputCore, UpdateCore, XofReaderCore,
+    },
+    crypto_common::{AlgorithmName, Block, BlockSizeUser},
+    generic_array::{ArrayLength, GenericArray},
+    typenum::{Diff, IsLess, Prod, Quot, Unsigned, B1, U256, U4, U512, U8},
+    HashMarker, Output, OutputSizeUser, Reset,
+};
+use std::fmt;
+use std::ops::{Div, Mul, Sub};
+use std::sync::Arc;
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::compress::Compressor;
+use crate::Error;
+
+pub mod state;
+
+/// Shape describes the sizes of a sumhash instance with n output words and m input bits,
+/// where `(N, M)` are given as typenum unsigned integers.
+pub trait Shape {
+    /// InputSize is the compressor input size in bytes, m/8.
+    type InputSize: ArrayLength<u8> + 'static;
+    /// OutputSize is the compressor output size (and digest size) in bytes, n*8.
+    type OutputSize: ArrayLength<u8> + 'static;
+    /// BlockSize is the number of message bytes absorbed per compression, m/8 - n*8.
+    /// It must be less than 256 bytes.
+    type BlockSize: ArrayLength<u8> + IsLess<U256, Output = B1> + 'static;
+}
+
+impl<N, M> Shape for (N, M)
+where
+    N: Unsigned + Mul<U8>,
+    M: Unsigned + Div<U8>,
+    Quot<M, U8>: ArrayLength<u8> + Sub<Prod<N, U8>> + 'static,
+    Prod<N, U8>: ArrayLength<u8> + 'static,
+    Diff<Quot<M, U8>, Prod<N, U8>>: ArrayLength<u8> + IsLess<U256, Output = B1> + 'static,
+{
+    type InputSize = Quot<M, U8>;
+    type OutputSize = Prod<N, U8>;
+    type BlockSize = Diff<Quot<M, U8>, Prod<N, U8>>;
+}
+
+/// Sumhash256Core is a sumhash core with 256 bit output (n=4, m=512), absorbing 32 byte blocks.
+pub type Sumhash256Core<C> = SumhashCore<C, U4, U512>;
+
+// The length suffix appended by the padding: the bit length of the message as 16 little endian bytes.
+const LENGTH_SUFFIX_SIZE: usize = 16;
+
+// The padding delimiters, which separate the fixed size digest from the extendable output.
+const FIXED_OUTPUT_DELIMITER: u8 = 0x01;
+const XOF_DELIMITER: u8 = 0x02;
+
+/// SumhashCore is a core implementation for the sumhash family of cryptographic hash functions,
+/// with `N` output words and `M` compressor input bits.
+///
+/// The compressor can be owned (e.g. `SumhashCore<Matrix, N, M>`), shared (`SumhashCore<Arc<Matrix>, N, M>`)
+/// or borrowed (`SumhashCore<&'a Matrix, N, M>`).
+pub struct SumhashCore<C: Compressor, N, M>
+where
+    (N, M): Shape,
+{
+    c: C,
+    h: GenericArray<u8, <(N, M) as Shape>::OutputSize>, // hash chain (from last compression, or IV)
+    len: u64,
+    salt: Option<GenericArray<u8, <(N, M) as Shape>::BlockSize>>,
+}
+
+impl<C: Compressor, N, M> SumhashCore<C, N, M>
+where
+    (N, M): Shape,
+{
+    /// new returns a SumhashCore using the compressor c.
+    /// It fails if c doesn't compress messages of m/8 bytes into n*8 bytes.
+    pub fn new(c: C) -> Result<Self, Error> {
+        Self::check_compressor(&c)?;
+        Ok(Self::new_unchecked(c, None))
+    }
+
+    /// with_salt returns a SumhashCore using the compressor c, with salt.
+    /// It fails if c doesn't compress messages of m/8 bytes into n*8 bytes.
+    pub fn with_salt(c: C, salt: impl Into<Block<Self>>) -> Result<Self, Error> {
+        Self::check_compressor(&c)?;
+        Ok(Self::new_unchecked(c, Some(salt.into())))
+    }
+
+    fn check_compressor(c: &C) -> Result<(), Error> {
+        let input_len = <(N, M) as Shape>::InputSize::USIZE;
+        let output_len = <(N, M) as Shape>::OutputSize::USIZE;
+        if <(N, M) as Shape>::BlockSize::USIZE < LENGTH_SUFFIX_SIZE {
+            return Err(Error::InvalidDimensions {
+                n: output_len / 8,
+                m: input_len * 8,
+            });
+        }
+        if c.input_len() != input_len {
+            return Err(Error::InputLength {
+                got: c.input_len(),
+                expected: input_len,
+            });
+        }
+        if c.output_len() != output_len {
+            return Err(Error::OutputLength {
+                got: c.output_len(),
+                expected: output_len,
+            });
+        }
+        Ok(())
+    }
+
+    pub(crate) fn new_unchecked(c: C, salt: Option<Block<Self>>) -> Self {
+        let mut s = Self {
+            c,
+            h: Default::default(),
+            salt,
+            len: 0,
+        };
+        s.reset();
+        s
+    }
+
+    fn compress_block(&mut self, data: &[u8]) {
+        let mut cin = GenericArray::<u8, <(N, M) as Shape>::InputSize>::default();
+        let hlen = self.h.len();
+        self.len += data.len() as u64;
+
+        cin[0..hlen].clone_from_slice(&self.h);
+        match self.salt {
+            Some(ref salt) => cin[hlen..]
+                .iter_mut()
+                .enumerate()
+                .for_each(|(i, val)| *val = data[i] ^ salt[i]),
+            None => cin[hlen..].clone_from_slice(data),
+        }
+
+        self.c.compress(&mut self.h, &cin);
+    }
+
+    // finalize pads the buffered data with the delimiter and the length suffix, and compresses it.
+    fn finalize(&mut self, buffer: &mut Buffer<Self>, delimiter: u8) {
+        let bitlen = (self.len + buffer.get_pos() as u64) << 3; // number of input bits written
+        let mut tmp = [0; LENGTH_SUFFIX_SIZE];
+        LittleEndian::write_u64(&mut tmp, bitlen);
+        buffer.digest_pad(delimiter, &tmp, |a| self.compress_block(a));
+    }
+
+    /// hash_many returns the digest of every input as if each was absorbed by a copy of this core and finalized.
+    ///
+    /// The inputs are processed together one block at a time with [`Compressor::compress_batch`], which
+    /// makes hashing many short inputs faster than hashing them one by one.
+    pub fn hash_many<T: AsRef<[u8]>>(&self, inputs: &[T]) -> Vec<Output<Self>> {
+        let bs = <(N, M) as Shape>::BlockSize::USIZE;
+        let hlen = self.h.len();
+        let ilen = hlen + bs;
+
+        // Every input is padded with the delimiter and the length suffix, as in finalize_fixed_core.
+        let blocks = |input: &[u8]| (input.len() + 1 + LENGTH_SUFFIX_SIZE).div_ceil(bs);
+        let rounds = inputs.iter().map(|input| blocks(input.as_ref())).max();
+
+        let mut out = vec![self.h.clone(); inputs.len()];
+        let mut active = Vec::with_capacity(inputs.len());
+        let mut src = Vec::with_capacity(inputs.len() * ilen);
+        let mut dst = Vec::with_capacity(inputs.len() * hlen);
+        for r in 0..rounds.unwrap_or(0) {
+            active.clear();
+            src.clear();
+            for (k, input) in inputs.iter().enumerate() {
+                let input = input.as_ref();
+                if r >= blocks(input) {
+                    continue;
+                }
+                active.push(k);
+                src.extend_from_slice(&out[k]);
+                let block = src.len();
+                src.resize(block + bs, 0);
+                self.padded_block(input, r, &mut src[block..]);
+            }
+
+            dst.resize(active.len() * hlen, 0);
+            self.c.compress_batch(&mut dst, &src);
+            active
+                .iter()
+                .zip(dst.chunks_exact(hlen))
+                .for_each(|(&k, h)| out[k].copy_from_slice(h));
+        }
+        out
+    }
+
+    // padded_block writes the salted r-th block of the padded input into block.
+    fn padded_block(&self, input: &[u8], r: usize, block: &mut [u8]) {
+        let bs = block.len();
+        let start = (r * bs).min(input.len());
+        let end = ((r + 1) * bs).min(input.len());
+        block[..end - start].copy_from_slice(&input[start..end]);
+        if input.len() >= r * bs && input.len() < (r + 1) * bs {
+            block[input.len() - r * bs] = FIXED_OUTPUT_DELIMITER;
+        }
+        if (input.len() + 1 + LENGTH_SUFFIX_SIZE).div_ceil(bs) == r + 1 {
+            let bitlen = (self.len + input.len() as u64) << 3;
+            LittleEndian::write_u64(&mut block[bs - LENGTH_SUFFIX_SIZE..], bitlen);
+        }
+        if let Some(ref salt) = self.salt {
+            block.iter_mut().zip(salt).for_each(|(b, s)| *b ^= s);
+        }
+    }
+}
+
+impl<C: Compressor, N, M> SumhashCore<Arc<C>, N, M>
+where
+    (N, M): Shape,
+{
+    /// from_arc returns a SumhashCore which shares the compressor c with other owners.
+    pub fn from_arc(c: Arc<C>) -> Result<Self, Error> {
+        Self::new(c)
+    }
+}
+
+impl<'a, C: Compressor, N, M> SumhashCore<&'a C, N, M>
+where
+    (N, M): Shape,
+{
+    /// from_ref returns a SumhashCore which borrows the compressor c.
+    pub fn from_ref(c: &'a C) -> Result<Self, Error> {
+        Self::new(c)
+    }
+}
+
+impl<C: Compressor, N, M> Reset for SumhashCore<C, N, M>
+where
+    (N, M): Shape,
+{
+    fn reset(&mut self) {
+        self.h = Default::default();
+        self.len = 0;
+        if self.salt.is_some() {
+            // Write an initial block of zeros, effectively
+            // prepending the salt to the input.
+            self.compress_block(&Block::<Self>::default());
+        }
+    }
+}
+
+impl<C: Compressor, N, M> Clone for SumhashCore<C, N, M>
+where
+    (N, M): Shape,
+{
+    fn clone(&self) -> Self {
+        Self {
+            c: self.c.clone(),
+            h: self.h.clone(),
+            len: self.len,
+            salt: self.salt.clone(),
+        }
+    }
+}
+
+impl<C: Compressor, N, M> fmt::Debug for SumhashCore<C, N, M>
+where
+    (N, M): Shape,
+{
+    // The salt and the chaining value are left out, since they may be secret.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SumhashCore")
+            .field("output_bits", &(<(N, M) as Shape>::OutputSize::USIZE * 8))
+            .field("len", &self.len)
+            .field("salted", &self.salt.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C: Compressor, N, M> AlgorithmName for SumhashCore<C, N, M>
+where
+    (N, M): Shape,
+{
+    fn write_alg_name(f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Sumhash{}", <(N, M) as Shape>::OutputSize::USIZE * 8)
+    }
+}
+
+impl<C: Compressor, N, M> HashMarker for SumhashCore<C, N, M> where (N, M): Shape {}
+
+impl<C: Compressor, N, M> BlockSizeUser for SumhashCore<C, N, M>
+where
+    (N, M): Shape,
+{
+    type BlockSize = <(N, M) as Shape>::BlockSize;
+}
+
+impl<C: Compressor, N, M> BufferKindUser for SumhashCore<C, N, M>
+where
+    (N, M): Shape,
+{
+    type BufferKind = Eager;
+}
+
+impl<C: Compressor, N, M> OutputSizeUser for SumhashCore<C, N, M>
+where
+    (N, M): Shape,
+{
+    type OutputSize = <(N, M) as Shape>::OutputSize;
+}
+
+impl<C: Compressor, N, M> FixedOutputCore for SumhashCore<C, N, M>
+where
+    (N, M): Shape,
+{
+    fn finalize_fixed_core(&mut self, buffer: &mut Buffer<Self>, out: &mut Output<Self>) {
+        self.finalize(buffer, FIXED_OUTPUT_DELIMITER);
+        out.copy_from_slice(&self.h);
+    }
+}
+
+impl<C: Compressor, N, M> ExtendableOutputCore for SumhashCore<C, N, M>
+where
+    (N, M): Shape,
+{
+    type ReaderCore = SumhashReaderCore<C, N, M>;
+
+    fn finalize_xof_core(&mut self, buffer: &mut Buffer<Self>) -> Self::ReaderCore {
+        self.finalize(buffer, XOF_DELIMITER);
+        SumhashReaderCore {
+            c: self.c.clone(),
+            h: self.h.clone(),
+            salt: self.salt.clone(),
+            counter: 0,
+        }
+    }
+}
+
+impl<C: Compressor, N, M> UpdateCore for SumhashCore<C, N, M>
+where
+    (N, M): Shape,
+{
+    fn update_blocks(&mut self, blocks: &[Block<Self>]) {
+        for b in blocks {
+            self.compress_block(b)
+        }
+    }
+}
+
+/// SumhashReaderCore is the extendable output of a SumhashCore.
+///
+/// The message is padded with a different delimiter than for the fixed size digest, so the extendable
+/// output never starts with the digest of the same message. The output is then produced n*8 bytes at a time,
+/// the k-th block being the compression of the final chaining value followed by a (salted) counter block,
+/// which holds k as a little endian u64 followed by zeros.
+pub struct SumhashReaderCore<C: Compressor, N, M>
+where
+    (N, M): Shape,
+{
+    c: C,
+    h: GenericArray<u8, <(N, M) as Shape>::OutputSize>, // final chaining value
+    salt: Option<GenericArray<u8, <(N, M) as Shape>::BlockSize>>,
+    counter: u64,
+}
+
+impl<C: Compressor, N, M> Clone for SumhashReaderCore<C, N, M>
+where
+    (N, M): Shape,
+{
+    fn clone(&self) -> Self {
+        Self {
+            c: self.c.clone(),
+            h: self.h.clone(),
+            salt: self.salt.clone(),
+            counter: self.counter,
+        }
+    }
+}
+
+impl<C: Compressor, N, M> fmt::Debug for SumhashReaderCore<C, N, M>
+where
+    (N, M): Shape,
+{
+    // The salt and the chaining value are left out, since they may be secret.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SumhashReaderCore")
+            .field("output_bits", &(<(N, M) as Shape>::OutputSize::USIZE * 8))
+            .field("counter", &self.counter)
+            .field("salted", &self.salt.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C: Compressor, N, M> BlockSizeUser for SumhashReaderCore<C, N, M>
+where
+    (N, M): Shape,
+{
+    type BlockSize = <(N, M) as Shape>::OutputSize;
+}
+
+impl<C: Compressor, N, M> XofReaderCore for SumhashReaderCore<C, N, M>
+where
+    (N, M): Shape,
+{
+    fn read_block(&mut self) -> Block<Self> {
+        let mut cin = GenericArray::<u8, <(N, M) as Shape>::InputSize>::default();
+        let hlen = self.h.len();
+        cin[..hlen].copy_from_slice(&self.h);
+        LittleEndian::write_u64(&mut cin[hlen..hlen + 8], self.counter);
+        if let Some(ref salt) = self.salt {
+            cin[hlen..].iter_mut().zip(salt).for_each(|(b, s)| *b ^= s);
+        }
+        self.counter += 1;
+
+        let mut out = Block::<Self>::default();
+        self.c.compress(&mut out, &cin);
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::compress::Matrix;
+    use digest::{
+        core_api::CoreWrapper,
+        typenum::{U16, U2048},
+        ExtendableOutput, FixedOutput, FixedOutputReset, Update, XofReader,
+    };
+
+    #[test]
+    fn sumhash256() {
+        let a = Matrix::random_from_seed("Algorand".as_bytes(), 4, 512);
+        assert!(Sumhash256Core::new(Matrix::random_from_seed(&[], 8, 1024)).is_err());
+
+        // The empty message is padded into a single block.
+        let mut cin = [0u8; 64];
+        cin[32] = 0x01;
+        let mut want = [0u8; 32];
+        a.compress(&mut want, &cin);
+
+        let mut h = CoreWrapper::from_core(Sumhash256Core::new(a.lookup_table()).unwrap());
+        assert_eq!(h.finalize_fixed_reset().as_slice(), want);
+
+        // Lookup table and matrix compressors agree on longer messages.
+        let input = [0x5au8; 1000];
+        h.update(&input);
+        let mut g = CoreWrapper::from_core(Sumhash256Core::from_ref(&a).unwrap());
+        g.update(&input);
+        assert_eq!(h.finalize_fixed(), g.finalize_fixed());
+    }
+
+    #[test]
+    fn sumhash1024() {
+        let a = Matrix::random_from_seed("Algorand".as_bytes(), 16, 2048);
+
+        // The empty message is padded into a single block.
+        let mut cin = [0u8; 256];
+        cin[128] = 0x01;
+        let mut want = [0u8; 128];
+        a.compress(&mut want, &cin);
+
+        let mut h =
+            CoreWrapper::from_core(SumhashCore::<_, U16, U2048>::new(a.lookup_table()).unwrap());
+        assert_eq!(h.finalize_fixed_reset().as_slice(), want);
+
+        // Lookup table and matrix compressors agree on messages spanning several blocks.
+        let input = [0xa5u8; 300];
+        h.update(&input);
+        let mut g = CoreWrapper::from_core(SumhashCore::<_, U16, U2048>::new(&a).unwrap());
+        g.update(&input);
+        assert_eq!(h.finalize_fixed(), g.finalize_fixed());
+    }
+
+    #[test]
+    fn hash_many() {
+        let a = Matrix::random_from_seed("Algorand".as_bytes(), 4, 512);
+        let at = a.lookup_table();
+        let inputs: Vec<Vec<u8>> = (0..200)
+            .map(|i| (0..i).map(|_| rand::random::<u8>()).collect())
+            .collect();
+
+        let salt = [0x42u8; 32];
+        let cores = [
+            Sumhash256Core::from_ref(&at).unwrap(),
+            Sumhash256Core::with_salt(&at, salt).unwrap(),
+        ];
+        for core in &cores {
+            let digests = core.hash_many(&inputs);
+            assert_eq!(digests.len(), inputs.len());
+            inputs.iter().zip(digests).for_each(|(input, digest)| {
+                let mut h = CoreWrapper::from_core(match core.salt {
+                    Some(_) => Sumhash256Core::with_salt(&a, salt).unwrap(),
+                    None => Sumhash256Core::from_ref(&a).unwrap(),
+                });
+                h.update(input);
+                assert_eq!(
+                    h.finalize_fixed(),
+                    digest,
+                    "input of length {}",
+                    input.len()
+                );
+            });
+        }
+        assert!(cores[0].hash_many::<&[u8]>(&[]).is_empty());
+    }
+
+    #[test]
+    fn xof() {
+        let a = Matrix::random_from_seed("Algorand".as_bytes(), 4, 512);
+
+        // The empty message is padded into a single block, with the XOF delimiter.
+        let mut cin = [0u8; 64];
+        cin[32] = XOF_DELIMITER;
+        let mut h = [0u8; 32];
+        a.compress(&mut h, &cin);
+        let mut want = [0u8; 96];
+        (0..3).for_each(|k| {
+            let mut cin = [0u8; 64];
+            cin[..32].copy_from_slice(&h);
+            cin[32] = k as u8;
+            a.compress(&mut want[32 * k..32 * (k + 1)], &cin);
+        });
+
+        let g = CoreWrapper::from_core(Sumhash256Core::from_ref(&a).unwrap());
+        let mut got = [0u8; 96];
+        g.finalize_xof().read(&mut got);
+        assert_eq!(got, want);
+
+        // The extendable output doesn't start with the fixed size digest.
+        let mut g = CoreWrapper::from_core(Sumhash256Core::from_ref(&a).unwrap());
+        assert_ne!(g.finalize_fixed_reset().as_slice(), &got[..32]);
+
+        // Reads can be split arbitrarily, and the salt changes the whole output.
+        let input = [0x5au8; 1000];
+        let salt = [0x42u8; 32];
+        for core in [
+            Sumhash256Core::from_ref(&a).unwrap(),
+            Sumhash256Core::with_salt(&a, salt).unwrap(),
+        ] {
+            let mut g = CoreWrapper::from_core(core);
+            g.update(&input);
+            let mut r = g.finalize_xof();
+            let mut split = [0u8; 96];
+            r.read(&mut split[..7]);
+            r.read(&mut split[7..50]);
+            r.read(&mut split[50..]);
+            assert_ne!(split, got);
+            got = split;
+        }
+        let mut g = CoreWrapper::from_core(Sumhash256Core::with_salt(&a, salt).unwrap());
+        g.update(&input);
+        let mut want = [0u8; 96];
+        g.finalize_xof_into(&mut want);
+        assert_eq!(got, want);
+    }
+}
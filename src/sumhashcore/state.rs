@@ -0,0 +1,326 @@
+//! Checkpointing of a hash computation.
+//!
+//! The state of a [`SumhashCore`] (and of the [`CoreWrapper`] around it) can be written with
+//! [`SumhashCore::write_state`] or [`write_hasher_state`], and later restored with
+//! [`SumhashCore::read_state`] or [`read_hasher_state`] to resume hashing where it stopped.
+//! The compressor isn't part of the state and has to be provided again when restoring it. The state records
+//! the seed fingerprint of the compressor though, and restoring it with a compressor whose fingerprint is
+//! different, or present on only one side, fails with [`Error::CompressorMismatch`].
+//!
+//! The state is encoded as follows, all integers being little-endian:
+//!
+//! | field        | size                                                          |
+//! |--------------|---------------------------------------------------------------|
+//! | magic        | 4 bytes, `SHST`                                               |
+//! | version      | u16, currently 1                                              |
+//! | n            | u32, number of compressor output words                        |
+//! | m            | u32, number of compressor input bits                          |
+//! | seed flag    | u8, 1 if the compressor has a seed fingerprint, 0 otherwise   |
+//! | fingerprint  | 32 bytes, the seed fingerprint, zeroed when absent            |
+//! | len          | u64, number of bytes compressed so far, salt block included   |
+//! | h            | n*8 bytes, the chaining value                                 |
+//! | salt flag    | u8, 1 if the core is salted, 0 otherwise                      |
+//! | salt         | m/8 - n*8 bytes, zeroed when absent                           |
+//! | buffered     | u8, number of buffered message bytes, less than m/8 - n*8     |
+//! | buffer       | the buffered message bytes                                    |
+//! | checksum     | 32 bytes, Shake256 of every preceding byte                    |
+//!
+//! Note that the state of a salted core contains its salt.
+use digest::{
+    core_api::CoreWrapper, crypto_common::Block, generic_array::GenericArray, typenum::Unsigned,
+    Update,
+};
+use std::io::{Read, Write};
+
+use super::{Shape, SumhashCore};
+use crate::compress::encoding::{ChecksumReader, ChecksumWriter};
+use crate::compress::Compressor;
+use crate::Error;
+
+const STATE_MAGIC: &[u8; 4] = b"SHST";
+const VERSION: u16 = 1;
+
+impl<C: Compressor, N, M> SumhashCore<C, N, M>
+where
+    (N, M): Shape,
+{
+    /// write_state encodes the state of the core into w, so that hashing can be resumed with read_state.
+    /// See the [`state`](crate::sumhashcore::state) module for the format.
+    pub fn write_state<W: Write>(&self, w: W) -> Result<(), Error> {
+        self.write_state_with_buffer(&[], w)
+    }
+
+    /// read_state decodes a state written with write_state from r, returning a core using the compressor c.
+    /// It fails if c doesn't compress messages of m/8 bytes into n*8 bytes or doesn't have the seed fingerprint
+    /// of the compressor that wrote the state, or if the state is from a hasher with buffered data, which has
+    /// to be restored with [`read_hasher_state`].
+    pub fn read_state<R: Read>(c: C, r: R) -> Result<Self, Error> {
+        let (core, buffer) = Self::read_state_with_buffer(c, r)?;
+        if !buffer.is_empty() {
+            return Err(Error::InvalidEncoding("unexpected buffered data"));
+        }
+        Ok(core)
+    }
+
+    fn write_state_with_buffer<W: Write>(&self, buffer: &[u8], w: W) -> Result<(), Error> {
+        let n = <(N, M) as Shape>::OutputSize::U32 / 8;
+        let m = <(N, M) as Shape>::InputSize::U32 * 8;
+
+        let mut w = ChecksumWriter::new(w);
+        w.write(STATE_MAGIC)?;
+        w.write(&VERSION.to_le_bytes())?;
+        w.write(&n.to_le_bytes())?;
+        w.write(&m.to_le_bytes())?;
+        let seed_fingerprint = self.c.seed_fingerprint();
+        w.write(&[seed_fingerprint.is_some() as u8])?;
+        w.write(&seed_fingerprint.copied().unwrap_or_default())?;
+        w.write(&self.len.to_le_bytes())?;
+        w.write(&self.h)?;
+        w.write(&[self.salt.is_some() as u8])?;
+        w.write(&self.salt.clone().unwrap_or_default())?;
+        // The buffer of a CoreWrapper always holds less than a block, which is less than 256 bytes.
+        w.write(&[buffer.len() as u8])?;
+        w.write(buffer)?;
+        w.finish()
+    }
+
+    fn read_state_with_buffer<R: Read>(c: C, r: R) -> Result<(Self, Vec<u8>), Error> {
+        Self::check_compressor(&c)?;
+
+        let mut r = ChecksumReader::new(r);
+        let mut magic = [0; 4];
+        r.read(&mut magic)?;
+        if &magic != STATE_MAGIC {
+            return Err(Error::InvalidEncoding("unexpected magic bytes"));
+        }
+
+        let mut version = [0; 2];
+        r.read(&mut version)?;
+        let version = u16::from_le_bytes(version);
+        if version != VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+
+        let mut dim = [0; 4];
+        r.read(&mut dim)?;
+        let n = u32::from_le_bytes(dim);
+        r.read(&mut dim)?;
+        let m = u32::from_le_bytes(dim);
+        if n != <(N, M) as Shape>::OutputSize::U32 / 8 || m != <(N, M) as Shape>::InputSize::U32 * 8
+        {
+            return Err(Error::InvalidEncoding(
+                "state of a different sumhash instance",
+            ));
+        }
+
+        let mut flag = [0; 1];
+        r.read(&mut flag)?;
+        let mut seed_fingerprint = [0; 32];
+        r.read(&mut seed_fingerprint)?;
+        let seed_fingerprint = match flag[0] {
+            0 => None,
+            1 => Some(seed_fingerprint),
+            _ => return Err(Error::InvalidEncoding("invalid seed fingerprint flag")),
+        };
+
+        let mut len = [0; 8];
+        r.read(&mut len)?;
+        let mut h = GenericArray::default();
+        r.read(&mut h)?;
+
+        let mut flag = [0; 1];
+        r.read(&mut flag)?;
+        let mut salt = Block::<Self>::default();
+        r.read(&mut salt)?;
+        let salt = match flag[0] {
+            0 => None,
+            1 => Some(salt),
+            _ => return Err(Error::InvalidEncoding("invalid salt flag")),
+        };
+
+        let mut buffered = [0; 1];
+        r.read(&mut buffered)?;
+        if buffered[0] as usize >= <(N, M) as Shape>::BlockSize::USIZE {
+            return Err(Error::InvalidEncoding("buffered data longer than a block"));
+        }
+        let mut buffer = vec![0; buffered[0] as usize];
+        r.read(&mut buffer)?;
+        r.finish()?;
+
+        // The fingerprint is only compared once the checksum is verified, so corrupted states aren't reported
+        // as a different compressor.
+        if seed_fingerprint.as_ref() != c.seed_fingerprint() {
+            return Err(Error::CompressorMismatch);
+        }
+
+        let core = Self {
+            c,
+            h,
+            len: u64::from_le_bytes(len),
+            salt,
+        };
+        Ok((core, buffer))
+    }
+}
+
+/// write_hasher_state encodes the state of hasher into w, including its buffered message bytes, so that
+/// hashing can be resumed with read_hasher_state.
+/// The hasher is consumed to access its buffer, and an equivalent one is returned to continue hashing.
+/// See the [`state`](crate::sumhashcore::state) module for the format.
+pub fn write_hasher_state<C: Compressor, N, M, W: Write>(
+    hasher: CoreWrapper<SumhashCore<C, N, M>>,
+    w: W,
+) -> Result<CoreWrapper<SumhashCore<C, N, M>>, Error>
+where
+    (N, M): Shape,
+{
+    let (core, buffer) = hasher.decompose();
+    core.write_state_with_buffer(buffer.get_data(), w)?;
+
+    let mut hasher = CoreWrapper::from_core(core);
+    hasher.update(buffer.get_data());
+    Ok(hasher)
+}
+
+/// read_hasher_state decodes a state written with write_hasher_state or
+/// [`write_state`](SumhashCore::write_state) from r, returning a hasher using the compressor c.
+/// It fails if c doesn't compress messages of m/8 bytes into n*8 bytes, or doesn't have the seed fingerprint of
+/// the compressor that wrote the state.
+pub fn read_hasher_state<C: Compressor, N, M, R: Read>(
+    c: C,
+    r: R,
+) -> Result<CoreWrapper<SumhashCore<C, N, M>>, Error>
+where
+    (N, M): Shape,
+{
+    let (core, buffer) = SumhashCore::read_state_with_buffer(c, r)?;
+    let mut hasher = CoreWrapper::from_core(core);
+    hasher.update(&buffer);
+    Ok(hasher)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::compress::encoding::CHECKSUM_SIZE;
+    use crate::compress::{ConstantTimeMatrix, Matrix};
+    use crate::sumhash512;
+    use crate::sumhash512core::Sumhash512Core;
+    use crate::sumhashcore::Sumhash256Core;
+    use digest::{core_api::UpdateCore, FixedOutput};
+
+    type Hasher<'a> = CoreWrapper<Sumhash512Core<&'a Matrix>>;
+
+    #[test]
+    fn resume() {
+        let input: Vec<u8> = (0..1000).map(|_| rand::random::<u8>()).collect();
+        let a = Matrix::random_from_seed("Algorand".as_bytes(), 8, 1024);
+        let at = a.lookup_table();
+
+        for salt in [None, Some([0x42u8; 64])] {
+            let core = || match salt {
+                Some(salt) => Sumhash512Core::with_salt(&at, salt).unwrap(),
+                None => Sumhash512Core::from_ref(&at).unwrap(),
+            };
+            let mut want = CoreWrapper::from_core(core());
+            want.update(&input);
+            let want = want.finalize_fixed();
+
+            // Split at block boundaries and in the middle of blocks.
+            for split in [0, 1, 63, 64, 65, 500, 1000] {
+                let mut h = CoreWrapper::from_core(core());
+                h.update(&input[..split]);
+
+                let mut buf = Vec::new();
+                let mut h = write_hasher_state(h, &mut buf).unwrap();
+                assert_eq!(
+                    buf.len(),
+                    4 + 2 + 4 + 4 + 1 + 32 + 8 + 64 + 1 + 64 + 1 + split % 64 + CHECKSUM_SIZE
+                );
+
+                // The returned hasher continues the computation.
+                h.update(&input[split..]);
+                assert_eq!(h.finalize_fixed(), want, "returned hasher, split {}", split);
+
+                let mut h: Hasher = read_hasher_state(&a, &buf[..]).unwrap();
+                h.update(&input[split..]);
+                assert_eq!(h.finalize_fixed(), want, "restored hasher, split {}", split);
+            }
+
+            // The core state is restored without the buffered bytes, at a block boundary.
+            let mut c = core();
+            c.update_blocks(&[GenericArray::clone_from_slice(&input[..64])]);
+            let mut buf = Vec::new();
+            c.write_state(&mut buf).unwrap();
+            let c = Sumhash512Core::read_state(&a, &buf[..]).unwrap();
+            let mut h = CoreWrapper::from_core(c);
+            h.update(&input[64..]);
+            assert_eq!(h.finalize_fixed(), want, "restored core");
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        let a = Matrix::random_from_seed("Algorand".as_bytes(), 8, 1024);
+        let mut h: Hasher = CoreWrapper::from_core(Sumhash512Core::from_ref(&a).unwrap());
+        h.update(b"abc");
+        let mut buf = Vec::new();
+        write_hasher_state(h, &mut buf).unwrap();
+
+        assert!(matches!(
+            Sumhash512Core::read_state(&a, &buf[..]),
+            Err(Error::InvalidEncoding(_))
+        ));
+        let truncated: Result<Hasher, _> = read_hasher_state(&a, &buf[..buf.len() - 1]);
+        assert!(matches!(truncated, Err(Error::InvalidEncoding(_))));
+
+        let mut flipped = buf.clone();
+        flipped[30] ^= 1;
+        let flipped: Result<Hasher, _> = read_hasher_state(&a, &flipped[..]);
+        assert!(matches!(flipped, Err(Error::ChecksumMismatch)));
+
+        let mut versioned = buf.clone();
+        versioned[4] = 2;
+        let versioned: Result<Hasher, _> = read_hasher_state(&a, &versioned[..]);
+        assert!(matches!(versioned, Err(Error::UnsupportedVersion(2))));
+
+        let mut flagged = buf.clone();
+        flagged[14] = 2;
+        let flagged: Result<Hasher, _> = read_hasher_state(&a, &flagged[..]);
+        assert!(matches!(flagged, Err(Error::InvalidEncoding(_))));
+
+        let b = Matrix::random_from_seed("Algorand".as_bytes(), 4, 512);
+        let other: Result<CoreWrapper<Sumhash256Core<_>>, _> = read_hasher_state(&b, &buf[..]);
+        assert!(matches!(other, Err(Error::InvalidEncoding(_))));
+    }
+
+    #[test]
+    fn compressor_mismatch() {
+        let a = Matrix::random_from_seed("Algorand".as_bytes(), 8, 1024);
+        let mut h: Hasher = CoreWrapper::from_core(Sumhash512Core::from_ref(&a).unwrap());
+        h.update(b"abc");
+        let mut buf = Vec::new();
+        write_hasher_state(h, &mut buf).unwrap();
+
+        // A matrix of the same dimensions generated from another seed computes another hash.
+        let b = Matrix::random_from_seed("Other".as_bytes(), 8, 1024);
+        let other: Result<Hasher, _> = read_hasher_state(&b, &buf[..]);
+        assert!(matches!(other, Err(Error::CompressorMismatch)));
+
+        // The fingerprint of a matrix can't be checked when it's unknown.
+        let mut rand = &[0x42u8; 8 * 1024 * 8][..];
+        let c = Matrix::random_matrix(&mut rand, 8, 1024);
+        let unknown: Result<Hasher, _> = read_hasher_state(&c, &buf[..]);
+        assert!(matches!(unknown, Err(Error::CompressorMismatch)));
+
+        // Every compressor built from the matrix has its fingerprint.
+        let ct = ConstantTimeMatrix::new(a.clone());
+        let mut restored: CoreWrapper<Sumhash512Core<_>> =
+            read_hasher_state(&ct, &buf[..]).unwrap();
+        restored.update(b"def");
+        assert_eq!(restored.finalize_fixed(), sumhash512("abcdef").into());
+        let nt = a.nibble_lookup_table();
+        let restored: Result<CoreWrapper<Sumhash512Core<_>>, _> = read_hasher_state(&nt, &buf[..]);
+        assert!(restored.is_ok());
+    }
+}
@@ -0,0 +1,65 @@
+//! Fixtures computed with go-algorand by the generator in `testdata/go-algorand`.
+//!
+//! The fixtures are generated by the Fixtures workflow, run by hand with the go-algorand commit to use, which
+//! also runs the tests using them. They're ignored otherwise, until the fixtures are checked in. See the
+//! generator for the format.
+use std::fs;
+
+use crate::merkle::Digest;
+
+// FIXTURES is the file written by the generator.
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/go-algorand.txt");
+
+// fixtures returns the fields following the kind of every fixture of that kind.
+pub(crate) fn fixtures(kind: &str) -> Vec<Vec<String>> {
+    let data = fs::read_to_string(FIXTURES).unwrap_or_else(|err| {
+        panic!(
+            "reading {}: {}, generate it with testdata/go-algorand",
+            FIXTURES, err
+        )
+    });
+    let fixtures: Vec<Vec<String>> = data
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .map(|line| line.split_whitespace().map(str::to_string).collect())
+        .filter(|fields: &Vec<String>| fields.first().map(String::as_str) == Some(kind))
+        .map(|fields| fields[1..].to_vec())
+        .collect();
+    assert!(!fixtures.is_empty(), "no {} fixtures in {}", kind, FIXTURES);
+    fixtures
+}
+
+// bytes decodes a byte string field.
+pub(crate) fn bytes(field: &str) -> Vec<u8> {
+    match field {
+        "-" => Vec::new(),
+        _ => hex::decode(field).unwrap(),
+    }
+}
+
+// digest decodes a digest field.
+pub(crate) fn digest(field: &str) -> Digest {
+    bytes(field).try_into().unwrap()
+}
+
+// uints decodes a list of integers.
+pub(crate) fn uints(field: &str) -> Vec<u64> {
+    match field {
+        "-" => Vec::new(),
+        _ => field.split(',').map(|x| x.parse().unwrap()).collect(),
+    }
+}
+
+// path decodes the path of a proof.
+pub(crate) fn path(field: &str) -> Vec<Option<Digest>> {
+    match field {
+        "-" => Vec::new(),
+        _ => field
+            .split(',')
+            .map(|sibling| match sibling {
+                "0" => None,
+                _ => Some(digest(sibling)),
+            })
+            .collect(),
+    }
+}
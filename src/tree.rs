@@ -0,0 +1,261 @@
+//! Tree hashing of large inputs with sumhash512, which can be computed in parallel.
+//!
+//! Sumhash512 chains the compression of every block of a message, so it can't use more than one core. The tree
+//! mode instead splits the input into chunks of CHUNK_SIZE bytes, hashes each of them into a leaf, and hashes
+//! the leaves pairwise up to a single root. Its digests are **not** sumhash512 digests of the input: they're a
+//! distinct hash function, only computed with sumhash512.
+//!
+//! - A leaf is `sumhash512(flags || chunk index as u64 LE || chunk)`, flags being LEAF.
+//! - A parent is `sumhash512(flags || left || right)`, flags being PARENT.
+//! - The root node has the ROOT flag in addition. An input of at most one chunk is hashed as a single leaf, and
+//!   the empty input is a single empty chunk.
+//!
+//! The tree is left-balanced: the left subtree of a node over k > 1 chunks holds the largest power of two number
+//! of chunks smaller than k, so every chunk but the last one is full and the shape only depends on the input
+//! length. The flags separate leaves from parents and the root from inner nodes, and the chunk indices bind
+//! the chunks to their positions.
+//!
+//! sumhash512_tree is the sequential reference. With the `rayon` feature, sumhash512_tree_parallel computes the
+//! same digest on the rayon thread pool. Both take the whole input in memory, which for large files is
+//! meant to be a memory map of the file. TreeHasher computes the digest sequentially from an input written
+//! incrementally, e.g. copied from a reader with `std::io::copy`, keeping a chunk and a node per level of the
+//! tree in memory.
+use std::io::{self, Write};
+
+use digest::{FixedOutput, Update};
+
+use crate::sumhash512core::{Sumhash512, DIGEST_SIZE};
+
+/// CHUNK_SIZE is the size in bytes of the chunks hashed into the leaves of the tree.
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+// LEAF, PARENT and ROOT are the domain separation flags prefixing the hashed nodes.
+const LEAF: u8 = 1;
+const PARENT: u8 = 2;
+const ROOT: u8 = 4;
+
+/// sumhash512_tree returns the tree mode digest of data, computed sequentially.
+pub fn sumhash512_tree(data: impl AsRef<[u8]>) -> [u8; DIGEST_SIZE] {
+    subtree(data.as_ref(), 0, ROOT)
+}
+
+/// sumhash512_tree_parallel returns the tree mode digest of data, hashing its subtrees in parallel with rayon.
+/// It's equal to sumhash512_tree(data).
+#[cfg(feature = "rayon")]
+pub fn sumhash512_tree_parallel(data: impl AsRef<[u8]>) -> [u8; DIGEST_SIZE] {
+    subtree_parallel(data.as_ref(), 0, ROOT)
+}
+
+/// TreeHasher computes the tree mode digest of an input written incrementally, sequentially. Its digests are
+/// those of sumhash512_tree over the concatenation of the written data.
+#[derive(Clone, Default)]
+pub struct TreeHasher {
+    // chunk holds the bytes of the last chunk, which is only hashed once more data follows it since it's the
+    // root if it's the only chunk.
+    chunk: Vec<u8>,
+    // chunks is the number of chunks hashed so far.
+    chunks: u64,
+    // stack holds the nodes of the complete subtrees over the chunks hashed so far, the largest first.
+    stack: Vec<[u8; DIGEST_SIZE]>,
+}
+
+impl TreeHasher {
+    /// new returns a TreeHasher of the empty input.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// finalize returns the tree mode digest of the data written so far.
+    pub fn finalize(mut self) -> [u8; DIGEST_SIZE] {
+        if self.stack.is_empty() {
+            return leaf(&self.chunk, 0, ROOT);
+        }
+        let mut node = leaf(&self.chunk, self.chunks, 0);
+        while let Some(left) = self.stack.pop() {
+            let flags = if self.stack.is_empty() { ROOT } else { 0 };
+            node = parent(&left, &node, flags);
+        }
+        node
+    }
+
+    // push_chunk hashes the full chunk, which isn't the last one, and merges the subtrees it completes.
+    fn push_chunk(&mut self) {
+        let mut node = leaf(&self.chunk, self.chunks, 0);
+        self.chunk.clear();
+        self.chunks += 1;
+        // A subtree over 2^k chunks is complete when the number of chunks is a multiple of 2^k, and as more
+        // data follows, it's the left subtree of a node.
+        (0..self.chunks.trailing_zeros()).for_each(|_| {
+            node = parent(&self.stack.pop().unwrap(), &node, 0);
+        });
+        self.stack.push(node);
+    }
+}
+
+impl Update for TreeHasher {
+    fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            if self.chunk.len() == CHUNK_SIZE {
+                self.push_chunk();
+            }
+            let n = (CHUNK_SIZE - self.chunk.len()).min(data.len());
+            self.chunk.extend_from_slice(&data[..n]);
+            data = &data[n..];
+        }
+    }
+}
+
+impl Write for TreeHasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Update::update(self, buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// subtree returns the node of the subtree over data, whose first chunk has the given index.
+fn subtree(data: &[u8], index: u64, flags: u8) -> [u8; DIGEST_SIZE] {
+    if data.len() <= CHUNK_SIZE {
+        return leaf(data, index, flags);
+    }
+    let (l, r, r_index) = split(data, index);
+    parent(&subtree(l, index, 0), &subtree(r, r_index, 0), flags)
+}
+
+// subtree_parallel is subtree, hashing the two children of the node in parallel.
+#[cfg(feature = "rayon")]
+fn subtree_parallel(data: &[u8], index: u64, flags: u8) -> [u8; DIGEST_SIZE] {
+    if data.len() <= CHUNK_SIZE {
+        return leaf(data, index, flags);
+    }
+    let (l, r, r_index) = split(data, index);
+    let (l, r) = rayon::join(
+        || subtree_parallel(l, index, 0),
+        || subtree_parallel(r, r_index, 0),
+    );
+    parent(&l, &r, flags)
+}
+
+// split splits data of more than one chunk into the data of the left and right subtrees, and returns the index of
+// the first chunk of the right subtree.
+fn split(data: &[u8], index: u64) -> (&[u8], &[u8], u64) {
+    let chunks = data.len().div_ceil(CHUNK_SIZE);
+    let left_chunks = 1 << (usize::BITS - 1 - (chunks - 1).leading_zeros());
+    let (l, r) = data.split_at(left_chunks * CHUNK_SIZE);
+    (l, r, index + left_chunks as u64)
+}
+
+// leaf hashes the chunk at the given index.
+fn leaf(chunk: &[u8], index: u64, flags: u8) -> [u8; DIGEST_SIZE] {
+    hash(&[&[LEAF | flags], &index.to_le_bytes(), chunk])
+}
+
+// parent hashes the nodes of two subtrees.
+fn parent(l: &[u8; DIGEST_SIZE], r: &[u8; DIGEST_SIZE], flags: u8) -> [u8; DIGEST_SIZE] {
+    hash(&[&[PARENT | flags], l, r])
+}
+
+fn hash(parts: &[&[u8]]) -> [u8; DIGEST_SIZE] {
+    let mut h = Sumhash512::default();
+    parts.iter().for_each(|part| h.update(part));
+    h.finalize_fixed().into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sumhash512;
+
+    #[test]
+    fn tree() {
+        let data: Vec<u8> = (0..5 * CHUNK_SIZE + 7).map(|i| (i % 251) as u8).collect();
+        let chunk = |i: usize| &data[i * CHUNK_SIZE..((i + 1) * CHUNK_SIZE).min(data.len())];
+        let leaf = |i: usize| sumhash512([&[1][..], &(i as u64).to_le_bytes(), chunk(i)].concat());
+        let parent =
+            |flags: u8, l: [u8; 64], r: [u8; 64]| sumhash512([&[flags][..], &l, &r].concat());
+
+        // The six chunks are split into a subtree of four chunks and one of two.
+        let left = parent(2, parent(2, leaf(0), leaf(1)), parent(2, leaf(2), leaf(3)));
+        let right = parent(2, leaf(4), leaf(5));
+        assert_eq!(sumhash512_tree(&data), parent(6, left, right));
+
+        // Three chunks are split into a subtree of two chunks and a single leaf.
+        let data = &data[..2 * CHUNK_SIZE + 1];
+        let want = parent(
+            6,
+            parent(2, leaf(0), leaf(1)),
+            sumhash512([&[1][..], &2u64.to_le_bytes(), &data[2 * CHUNK_SIZE..]].concat()),
+        );
+        assert_eq!(sumhash512_tree(data), want);
+
+        // A single chunk is a root leaf.
+        let want = sumhash512([&[5][..], &0u64.to_le_bytes(), chunk(0)].concat());
+        assert_eq!(sumhash512_tree(chunk(0)), want);
+        assert_eq!(sumhash512_tree([]), sumhash512([5, 0, 0, 0, 0, 0, 0, 0, 0]));
+        assert_ne!(sumhash512_tree(chunk(0)), sumhash512(chunk(0)));
+    }
+
+    #[test]
+    fn streaming() {
+        let data: Vec<u8> = (0..9 * CHUNK_SIZE + 3)
+            .map(|_| rand::random::<u8>())
+            .collect();
+        for len in [
+            0,
+            1,
+            CHUNK_SIZE - 1,
+            CHUNK_SIZE,
+            CHUNK_SIZE + 1,
+            2 * CHUNK_SIZE,
+            3 * CHUNK_SIZE,
+            4 * CHUNK_SIZE,
+            4 * CHUNK_SIZE + 1,
+            8 * CHUNK_SIZE,
+            data.len(),
+        ] {
+            let want = sumhash512_tree(&data[..len]);
+            for split in [len, 1, 1000, CHUNK_SIZE, CHUNK_SIZE + 7] {
+                let mut h = TreeHasher::new();
+                data[..len]
+                    .chunks(split.max(1))
+                    .for_each(|part| h.update(part));
+                assert_eq!(h.finalize(), want, "len {}, split {}", len, split);
+            }
+
+            let mut h = TreeHasher::new();
+            io::copy(&mut &data[..len], &mut h).unwrap();
+            assert_eq!(h.finalize(), want, "len {}, copied", len);
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel() {
+        let data: Vec<u8> = (0..9 * CHUNK_SIZE + 3)
+            .map(|_| rand::random::<u8>())
+            .collect();
+        [
+            0,
+            1,
+            CHUNK_SIZE - 1,
+            CHUNK_SIZE,
+            CHUNK_SIZE + 1,
+            2 * CHUNK_SIZE,
+            3 * CHUNK_SIZE,
+            8 * CHUNK_SIZE,
+            data.len(),
+        ]
+        .iter()
+        .for_each(|&len| {
+            assert_eq!(
+                sumhash512_tree_parallel(&data[..len]),
+                sumhash512_tree(&data[..len]),
+                "len {}",
+                len
+            )
+        });
+    }
+}
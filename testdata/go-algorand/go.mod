@@ -0,0 +1,8 @@
+module github.com/dragmz/sumhash/testdata/go-algorand
+
+go 1.20
+
+require github.com/algorand/go-algorand v0.0.0
+
+// The generator is built against a go-algorand checkout, whose libsodium has to be built first.
+replace github.com/algorand/go-algorand => ../../go-algorand
@@ -0,0 +1,225 @@
+// Command go-algorand writes the fixtures of the sumhash tests computed with go-algorand to the standard output.
+//
+// It's built against a go-algorand checkout next to the sumhash repository, whose libsodium has to be built
+// first, as in the go-algorand-fixtures workflow, which is run by hand with the go-algorand commit to check out:
+//
+//	go mod tidy
+//	go run . -commit $(git -C ../../go-algorand rev-parse HEAD) -algod https://mainnet-api.algonode.cloud \
+//		-rounds 30000000,40000000 > ../go-algorand.txt
+//
+// The commit is written in the header of the fixtures, so that they can be regenerated with the same
+// go-algorand.
+//
+// With -algod, the reveals of the state proofs attesting the given rounds of the network of that node are written
+// along with the commitments they're proven against.
+//
+// Every fixture is a line of space separated fields starting with its kind. Byte strings are in hex and lists
+// are comma separated, "-" standing for an empty string or list and "0" for a missing sibling in a proof path.
+package main
+
+import (
+	"encoding/binary"
+	"encoding/hex"
+	"encoding/json"
+	"flag"
+	"fmt"
+	"net/http"
+	"os"
+	"sort"
+	"strconv"
+	"strings"
+
+	"github.com/algorand/go-algorand/crypto"
+	"github.com/algorand/go-algorand/crypto/merklearray"
+	"github.com/algorand/go-algorand/crypto/stateproof"
+	"github.com/algorand/go-algorand/protocol"
+)
+
+// testElement is hashed as its little-endian encoding with the "TE" prefix, like TestElement in the Rust tests.
+type testElement uint64
+
+func (e testElement) ToBeHashed() (protocol.HashID, []byte) {
+	b := make([]byte, 8)
+	binary.LittleEndian.PutUint64(b, uint64(e))
+	return protocol.HashID("TE"), b
+}
+
+// testArray holds the test elements 0 to its length.
+type testArray uint64
+
+func (a testArray) Length() uint64 {
+	return uint64(a)
+}
+
+func (a testArray) Marshal(pos uint64) (crypto.Hashable, error) {
+	return testElement(pos), nil
+}
+
+var factory = crypto.HashFactory{HashType: crypto.Sumhash}
+
+func main() {
+	commit := flag.String("commit", "", "go-algorand commit the generator is built against")
+	algod := flag.String("algod", "", "URL of the algod node to read the state proofs from, none to skip them")
+	token := flag.String("token", "", "API token of the algod node")
+	rounds := flag.String("rounds", "", "comma separated rounds attested by the state proofs")
+	flag.Parse()
+	if *commit == "" {
+		check(fmt.Errorf("the go-algorand commit is required"))
+	}
+	if *algod != "" && *rounds == "" {
+		check(fmt.Errorf("the rounds of the state proofs are required"))
+	}
+
+	fmt.Printf("# Generated by testdata/go-algorand with go-algorand %s, do not edit.\n", *commit)
+	for n := uint64(1); n <= 20; n++ {
+		writeTree(n)
+		writeVectorCommitment(n)
+	}
+	if *algod != "" {
+		for _, round := range strings.Split(*rounds, ",") {
+			r, err := strconv.ParseUint(round, 10, 64)
+			check(err)
+			writeStateProof(strings.TrimSuffix(*algod, "/"), *token, r)
+		}
+	}
+}
+
+// writeTree writes the root of the merklearray tree of n test elements and proofs of some of them.
+func writeTree(n uint64) {
+	tree, err := merklearray.Build(testArray(n), factory)
+	check(err)
+	fmt.Printf("tree %d %s\n", n, bytes(tree.Root()))
+	for _, idxs := range indexSets(n) {
+		// Prove sorts the indices, which are written first.
+		line := fmt.Sprintf("proof %d %s", n, uints(idxs))
+		proof, err := tree.Prove(idxs)
+		check(err)
+		fmt.Printf("%s %d %s\n", line, proof.TreeDepth, path(proof.Path))
+	}
+}
+
+// writeVectorCommitment writes the root of the vector commitment tree of n test elements, proofs of some of
+// them, and the single leaf proof of each of them with its fixed length representation.
+func writeVectorCommitment(n uint64) {
+	tree, err := merklearray.BuildVectorCommitmentTree(testArray(n), factory)
+	check(err)
+	fmt.Printf("vc %d %s\n", n, bytes(tree.Root()))
+	for _, idxs := range indexSets(n) {
+		line := fmt.Sprintf("vcproof %d %s", n, uints(idxs))
+		proof, err := tree.Prove(idxs)
+		check(err)
+		fmt.Printf("%s %d %s\n", line, proof.TreeDepth, path(proof.Path))
+	}
+	for i := uint64(0); i < n; i++ {
+		proof, err := tree.ProveSingleLeaf(i)
+		check(err)
+		fmt.Printf("single %d %d %d %s %s\n", n, i, proof.TreeDepth, path(proof.Path),
+			bytes(proof.GetFixedLengthHashableRepresentation()))
+	}
+}
+
+// writeStateProof writes the commitments of the state proof attesting round, and its reveals with the fields
+// and the msgpack encoding of their participant and signature slot. The reveals start with the last round
+// attested by the state proof, like its commitments.
+func writeStateProof(algod, token string, round uint64) {
+	var resp struct {
+		Message struct {
+			VotersCommitment  []byte
+			LastAttestedRound uint64
+		}
+		StateProof []byte
+	}
+	get(fmt.Sprintf("%s/v2/stateproofs/%d", algod, round), token, &resp)
+	var sp stateproof.StateProof
+	check(protocol.Decode(resp.StateProof, &sp))
+
+	fmt.Printf("stateproof %d %s %s %d %s %d %s\n", resp.Message.LastAttestedRound,
+		bytes(resp.Message.VotersCommitment), bytes(sp.SigCommit),
+		sp.PartProofs.TreeDepth, path(sp.PartProofs.Path), sp.SigProofs.TreeDepth, path(sp.SigProofs.Path))
+
+	positions := make([]uint64, 0, len(sp.Reveals))
+	for pos := range sp.Reveals {
+		positions = append(positions, pos)
+	}
+	sort.Slice(positions, func(i, j int) bool { return positions[i] < positions[j] })
+	for _, pos := range positions {
+		r := sp.Reveals[pos]
+		sig := r.SigSlot.Sig
+		ct, err := sig.Signature.GetFixedLengthHashableRepresentation()
+		check(err)
+		fmt.Printf("reveal %d %d %d %s %d %d %s %s %d %d %s %s %s %s\n", resp.Message.LastAttestedRound,
+			pos, r.Part.Weight, bytes(r.Part.PK.Commitment[:]), r.Part.PK.KeyLifetime,
+			r.SigSlot.L, bytes(sig.Signature), bytes(ct), sig.VectorCommitmentIndex,
+			sig.Proof.TreeDepth, path(sig.Proof.Path), bytes(sig.VerifyingKey.PublicKey[:]),
+			bytes(protocol.Encode(&r.Part)), bytes(protocol.Encode(&r.SigSlot)))
+	}
+}
+
+// get decodes the JSON response of the algod node to a GET of url into v.
+func get(url, token string, v interface{}) {
+	req, err := http.NewRequest(http.MethodGet, url, nil)
+	check(err)
+	if token != "" {
+		req.Header.Set("X-Algo-API-Token", token)
+	}
+	resp, err := http.DefaultClient.Do(req)
+	check(err)
+	defer resp.Body.Close()
+	if resp.StatusCode != http.StatusOK {
+		check(fmt.Errorf("GET %s: %s", url, resp.Status))
+	}
+	check(json.NewDecoder(resp.Body).Decode(v))
+}
+
+// indexSets returns sets of positions among n elements to prove, starting with no positions.
+func indexSets(n uint64) [][]uint64 {
+	sets := [][]uint64{{}, {0}, {n - 1}}
+	var all, some []uint64
+	for i := uint64(0); i < n; i++ {
+		all = append(all, i)
+		if i%3 != 1 {
+			some = append(some, n-1-i)
+		}
+	}
+	return append(sets, all, some)
+}
+
+func bytes(b []byte) string {
+	if len(b) == 0 {
+		return "-"
+	}
+	return hex.EncodeToString(b)
+}
+
+func uints(v []uint64) string {
+	if len(v) == 0 {
+		return "-"
+	}
+	s := make([]string, len(v))
+	for i, x := range v {
+		s[i] = fmt.Sprint(x)
+	}
+	return strings.Join(s, ",")
+}
+
+func path(p []crypto.GenericDigest) string {
+	if len(p) == 0 {
+		return "-"
+	}
+	s := make([]string, len(p))
+	for i, d := range p {
+		if len(d) == 0 {
+			s[i] = "0"
+		} else {
+			s[i] = hex.EncodeToString(d)
+		}
+	}
+	return strings.Join(s, ",")
+}
+
+func check(err error) {
+	if err != nil {
+		fmt.Fprintln(os.Stderr, err)
+		os.Exit(1)
+	}
+}